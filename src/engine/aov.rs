@@ -0,0 +1,156 @@
+use crate::{
+    common::*,
+    image::{FloatImage, Image},
+};
+
+/// Arbitrary output variable: buffer rendered in the same pass as the shaded
+/// colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    /// Shaded color averaged in floating point, before being rounded to 8 bits
+    Color,
+    /// Distance from the camera along the primary ray
+    Depth,
+    /// World space normal facing the camera
+    Normal,
+    /// Index of the hit object in the scene, starting at 1
+    ObjectId,
+    /// Diffuse color of the surface, without lighting
+    Albedo,
+    /// Fraction of the lights reaching the surface
+    Shadow,
+    /// Contribution of a single light
+    Light(usize),
+}
+
+/// Values of the buffers at the point hit by a ray
+pub(super) struct AovSample {
+    pub color: (f64, f64, f64),
+    pub depth: f64,
+    pub normal: (f64, f64, f64),
+    pub object: Option<usize>,
+    pub albedo: (f64, f64, f64),
+    pub lit: f64,
+    pub lights: Vec<(f64, f64, f64)>,
+}
+
+impl AovSample {
+    /// Values of a ray hitting nothing
+    pub fn miss() -> AovSample {
+        AovSample {
+            color: (0.0, 0.0, 0.0),
+            depth: f64::INFINITY,
+            normal: (0.0, 0.0, 0.0),
+            object: None,
+            albedo: (0.0, 0.0, 0.0),
+            lit: 0.0,
+            lights: Vec::new(),
+        }
+    }
+}
+
+impl Aov {
+    /// Parse a name as given by `name`, `lights` standing for every light of
+    /// the scene
+    pub fn parse(name: &str, nb_lights: usize) -> Option<Vec<Aov>> {
+        Some(match name {
+            "color" => vec![Aov::Color],
+            "depth" => vec![Aov::Depth],
+            "normal" => vec![Aov::Normal],
+            "id" => vec![Aov::ObjectId],
+            "albedo" => vec![Aov::Albedo],
+            "shadow" => vec![Aov::Shadow],
+            "lights" => (0..nb_lights).map(Aov::Light).collect(),
+            _ => vec![Aov::Light(name.strip_prefix("light")?.parse().ok()?)],
+        })
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Aov::Color => "color".to_string(),
+            Aov::Depth => "depth".to_string(),
+            Aov::Normal => "normal".to_string(),
+            Aov::ObjectId => "id".to_string(),
+            Aov::Albedo => "albedo".to_string(),
+            Aov::Shadow => "shadow".to_string(),
+            Aov::Light(i) => format!("light{}", i),
+        }
+    }
+
+    /// Whether the samples of a pixel are averaged, rather than only the
+    /// first one being kept where averaging gives meaningless values
+    pub(super) fn is_averaged(&self) -> bool {
+        !matches!(self, Aov::Depth | Aov::ObjectId)
+    }
+
+    pub(super) fn value(&self, sample: &AovSample) -> (f64, f64, f64) {
+        match self {
+            Aov::Color => sample.color,
+            Aov::Depth => (sample.depth, sample.depth, sample.depth),
+            Aov::Normal => sample.normal,
+            Aov::ObjectId => {
+                let id = sample.object.map_or(0.0, |i| (i + 1) as f64);
+                (id, id, id)
+            }
+            Aov::Albedo => sample.albedo,
+            Aov::Shadow => (sample.lit, sample.lit, sample.lit),
+            Aov::Light(i) => sample.lights.get(*i).copied().unwrap_or((0.0, 0.0, 0.0)),
+        }
+    }
+
+    /// Viewable version of a buffer: depth goes from white to black as
+    /// objects get further, normals are mapped from [-1, 1] to colors, and
+    /// objects get arbitrary distinct colors
+    pub fn to_image(self, buffer: &FloatImage) -> Image {
+        let channel = |v: f64| (v.clamp(0.0, 1.0) * 255.0) as u8;
+
+        match self {
+            Aov::Depth => {
+                let max = buffer
+                    .values()
+                    .map(|v| v.0)
+                    .filter(|d| d.is_finite())
+                    .fold(0.0, f64::max);
+                buffer.to_image(|(d, _, _)| {
+                    let v = if d.is_finite() && max > 0.0 {
+                        channel(1.0 - d / max * 0.9)
+                    } else {
+                        0
+                    };
+                    Color(v, v, v)
+                })
+            }
+            Aov::Normal => buffer.to_image(|(x, y, z)| {
+                Color(
+                    channel((x + 1.0) / 2.0),
+                    channel((y + 1.0) / 2.0),
+                    channel((z + 1.0) / 2.0),
+                )
+            }),
+            Aov::ObjectId => buffer.to_image(|(id, _, _)| match id as u32 {
+                0 => BLACK,
+                id => {
+                    let hash = id.wrapping_mul(2_654_435_761);
+                    Color((hash >> 24) as u8, (hash >> 16) as u8, (hash >> 8) as u8)
+                }
+            }),
+            _ => buffer.quantize(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Aov::parse("lights", 2),
+            Some(vec![Aov::Light(0), Aov::Light(1)])
+        );
+        assert_eq!(Aov::parse("light3", 2), Some(vec![Aov::Light(3)]));
+        assert_eq!(Aov::parse("id", 2).unwrap()[0].name(), "id");
+        assert_eq!(Aov::parse("beauty", 2), None);
+    }
+}
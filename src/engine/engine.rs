@@ -4,26 +4,53 @@ use rand::Rng;
 
 use crate::{
     common::*,
-    image::Image,
+    image::{self, DenoiseOptions, FloatImage, Image, Region},
+    post::{self, Effect},
     scene::{Camera, Light, Object, ObjectContainer, Ray, Scene},
+    sink::FrameSink,
 };
 
+use super::aov::{Aov, AovSample};
 use super::render::*;
 
-const ANTI_ALIASING_NB: i32 = 1;
-const ANTI_ALIASING_DELTA: f64 = 0.001;
+const ANTI_ALIASING_NB: usize = 1;
+
+/// Red, green and blue light, 1.0 being full white
+type Radiance = (f64, f64, f64);
+
+/// Channels of a color as floating point, 255 giving 1.0
+fn radiance(Color(r, g, b): Color) -> Radiance {
+    (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0)
+}
+
+fn add((ar, ag, ab): Radiance, (br, bg, bb): Radiance) -> Radiance {
+    (ar + br, ag + bg, ab + bb)
+}
 
 enum RenderingMode {
     Intersect,
     Diffuse,
     Specular,
     Ambient((f64, f64, f64)),
+    AmbientOcclusion {
+        ambient_light: (f64, f64, f64),
+        samples: usize,
+        radius: f64,
+    },
+    Environment {
+        strength: f64,
+        samples: usize,
+    },
     Reflection,
 }
 
 pub struct Engine {
     scene: Scene,
     mode: Vec<RenderingMode>,
+    samples: usize,
+    shutter: f64,
+    denoise: Option<DenoiseOptions>,
+    post: Vec<Effect>,
 }
 
 impl Engine {
@@ -31,9 +58,17 @@ impl Engine {
         Engine {
             scene,
             mode: Vec::new(),
+            samples: ANTI_ALIASING_NB,
+            shutter: 0.0,
+            denoise: None,
+            post: Vec::new(),
         }
     }
 
+    pub fn nb_lights(&self) -> usize {
+        self.scene.lights.len()
+    }
+
     pub fn reset_mode(&mut self) -> &mut Self {
         self.mode = Vec::new();
         self
@@ -59,94 +94,276 @@ impl Engine {
         self
     }
 
+    pub fn set_ambient_occlusion(
+        &mut self,
+        val: (f64, f64, f64),
+        samples: usize,
+        radius: f64,
+    ) -> &mut Self {
+        self.mode.push(RenderingMode::AmbientOcclusion {
+            ambient_light: val,
+            samples,
+            radius,
+        });
+        self
+    }
+
+    /// Light objects with the scene background, sampled over the hemisphere
+    pub fn set_environment_lighting(&mut self, strength: f64, samples: usize) -> &mut Self {
+        self.mode
+            .push(RenderingMode::Environment { strength, samples });
+        self
+    }
+
+    /// Render `width` x `height` pixels, the camera seeing the same view
+    #[cfg(any(feature = "preview", test))]
+    pub fn set_resolution(&mut self, width: usize, height: usize) -> &mut Self {
+        self.scene.cam.width = width;
+        self.scene.cam.height = height;
+        self
+    }
+
+    /// Number of rays cast per pixel, spread over the pixel and the lens
+    pub fn set_samples(&mut self, samples: usize) -> &mut Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    /// Fraction of the interval between two frames during which the shutter
+    /// is open, 0 disabling motion blur
+    pub fn set_motion_blur(&mut self, shutter: f64) -> &mut Self {
+        self.shutter = shutter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Smooth the noise of every rendered image, guided by the normals and
+    /// albedo of the surfaces
+    pub fn set_denoising(&mut self, options: DenoiseOptions) -> &mut Self {
+        self.denoise = Some(options);
+        self
+    }
+
+    /// Effects applied in order to every rendered image
+    pub fn set_post_processing(&mut self, effects: Vec<Effect>) -> &mut Self {
+        self.post = effects;
+        self
+    }
+
     pub fn set_reflection(&mut self) -> &mut Self {
         self.mode.push(RenderingMode::Reflection);
         self
     }
 
-    pub fn travelling<F: FnMut(&mut Camera)>(
+    /// Render `nb_frames` frames, moving the camera with `move_cam` after each
+    /// one, and deliver them to `sink` as they are done
+    pub fn travelling<F: FnMut(&mut Camera), S: FrameSink + ?Sized>(
         &mut self,
         move_cam: &mut F,
         nb_frames: usize,
-    ) -> Vec<Image> {
-        let mut res = Vec::new();
-
-        for i in 1..=nb_frames {
-            println!("Processing frame {}/{}", i, nb_frames);
-            res.push(self.render());
-            move_cam(&mut self.scene.cam);
+        sink: &mut S,
+    ) -> std::io::Result<()> {
+        for i in 0..nb_frames {
+            println!("Processing frame {}/{}", i + 1, nb_frames);
+            self.shoot(move_cam, i, sink)?;
         }
 
-        res
+        sink.finish()
     }
 
-    pub fn render_growth<F: FnMut(&mut Camera)>(
+    /// Render `nb_frames` frames while objects grow, `grow` building the
+    /// growing objects at a time going from 0 to 1. They are rendered along
+    /// with the objects already in the scene.
+    pub fn render_growth<
+        F: FnMut(&mut Camera),
+        G: FnMut(f64) -> ObjectContainer,
+        S: FrameSink + ?Sized,
+    >(
         &mut self,
         move_cam: &mut F,
         nb_frames: usize,
-        steps: &mut Vec<ObjectContainer>,
-    ) -> Vec<Image> {
-        let mut res = Vec::new();
-
-        let nb = steps.len();
-        for i in 1..=nb {
-            println!("Rendering step {}/{}", i, nb);
-            self.scene.objects = steps.remove(0);
-            for _ in 0..(if steps.is_empty() { 4 } else { 1 }) {
-                let mut step = self.travelling(move_cam, nb_frames);
-                res.append(&mut step);
+        grow: &mut G,
+        sink: &mut S,
+    ) -> std::io::Result<()> {
+        let nb_static = self.scene.objects.len();
+
+        for i in 0..nb_frames {
+            let time = i as f64 / (nb_frames.max(2) - 1) as f64;
+            println!("Growing frame {}/{}", i + 1, nb_frames);
+
+            if !sink.has_frame(i) {
+                self.scene.objects.truncate(nb_static);
+                self.scene.objects.append(&mut grow(time));
             }
+            self.shoot(move_cam, i, sink)?;
         }
 
-        res
+        sink.finish()
     }
 
-    pub fn render(&self) -> Image {
-        let mut res = Image::new(self.scene.cam.height, self.scene.cam.width);
-        for x in 0..self.scene.cam.height {
-            for y in 0..self.scene.cam.width {
-                let mut rng = rand::thread_rng();
-                let (mut r, mut g, mut b) = (0, 0, 0);
+    /// Render the `index`-th frame of an animation unless the sink already has
+    /// it, then move the camera to the next frame
+    fn shoot<F: FnMut(&mut Camera), S: FrameSink + ?Sized>(
+        &mut self,
+        move_cam: &mut F,
+        index: usize,
+        sink: &mut S,
+    ) -> std::io::Result<()> {
+        if sink.has_frame(index) {
+            println!("Frame {} already rendered", index + 1);
+            move_cam(&mut self.scene.cam);
+            return Ok(());
+        }
 
-                for _ in 0..ANTI_ALIASING_NB {
-                    let origin = self.scene.cam.get_pixel_pos(x, y);
+        if self.shutter > 0.0 {
+            // Move the camera ahead of time to know where it goes during the frame
+            let start = self.scene.cam.key();
+            move_cam(&mut self.scene.cam);
+            let end = self.scene.cam.key();
 
-                    let (dx, dy, dz) = (
-                        (rng.gen::<f64>() - 0.5) * ANTI_ALIASING_DELTA,
-                        (rng.gen::<f64>() - 0.5) * ANTI_ALIASING_DELTA,
-                        (rng.gen::<f64>() - 0.5) * ANTI_ALIASING_DELTA,
-                    );
+            self.scene.cam.look(&start);
+            self.scene.cam.set_motion(Some(end));
+            let frame = self.render(None);
 
-                    let direction = (Vector::from(self.scene.cam.pos, origin)
-                        + Vector::new(dx, dy, dz))
-                    .normalize();
+            self.scene.cam.set_motion(None);
+            self.scene.cam.look(&end);
+            sink.push(index, frame)
+        } else {
+            sink.push(index, self.render(None))?;
+            move_cam(&mut self.scene.cam);
+            Ok(())
+        }
+    }
 
-                    let ray = Ray {
-                        energy: 1.0,
-                        origin,
-                        direction,
-                    };
+    /// Render the whole image, or only the pixels of `region` leaving the rest
+    /// black
+    pub fn render(&self, region: Option<Region>) -> Image {
+        let (width, height) = (self.scene.cam.width, self.scene.cam.height);
+
+        match region {
+            Some(region) => {
+                let region = region.clip(width, height);
+                let mut res = Image::new(width, height);
+                res.paste(&self.render_cropped(region), region.x, region.y);
+                res
+            }
+            None => self.render_cropped(Region::new(0, 0, width, height)),
+        }
+    }
 
-                    if let Some(c) = self.cast_ray(ray) {
-                        r += c.0 as i32;
-                        g += c.1 as i32;
-                        b += c.2 as i32;
-                    }
+    /// Render the pixels of `region` only, as an image of the size of the
+    /// region
+    pub fn render_cropped(&self, region: Region) -> Image {
+        self.render_aovs(region, &[]).0
+    }
+
+    /// Render the pixels of `region` along with a buffer for each of `aovs`,
+    /// all of the size of the region
+    pub fn render_aovs(&self, region: Region, aovs: &[Aov]) -> (Image, Vec<FloatImage>) {
+        // The denoiser needs its own buffers, rendered after the requested ones
+        let mut all = aovs.to_vec();
+        if self.denoise.is_some() {
+            for &aov in [Aov::Normal, Aov::Albedo].iter() {
+                if !all.contains(&aov) {
+                    all.push(aov);
                 }
-                let c = Color(
-                    (r / ANTI_ALIASING_NB) as u8,
-                    (g / ANTI_ALIASING_NB) as u8,
-                    (b / ANTI_ALIASING_NB) as u8,
-                );
-                res.set(x, y, c)
             }
         }
-        res
+
+        let frame = (self.scene.cam.width, self.scene.cam.height);
+        let region = region.clip(frame.0, frame.1);
+        // The denoiser and bloom read pixels around the region, which are
+        // traced too so that tiles match the whole image
+        let margin = self.denoise.map_or(0, |options| options.reach())
+            + self.post.iter().map(Effect::reach).sum::<usize>();
+        let traced = region.grow(margin).clip(frame.0, frame.1);
+
+        let mut color = FloatImage::new(traced.width, traced.height);
+        let mut buffers = vec![FloatImage::new(traced.width, traced.height); all.len()];
+        let mut rng = rand::thread_rng();
+
+        for y in 0..traced.height {
+            for x in 0..traced.width {
+                let (c, values) = self.pixel(traced.x + x, traced.y + y, &all, &mut rng);
+                color.set(x, y, c);
+                for (buffer, value) in buffers.iter_mut().zip(values) {
+                    buffer.set(x, y, value);
+                }
+            }
+        }
+
+        if let Some(options) = &self.denoise {
+            let buffer = |aov| &buffers[all.iter().position(|&a| a == aov).unwrap()];
+            color = image::denoise(&color, buffer(Aov::Normal), buffer(Aov::Albedo), options);
+        }
+        let color = post::apply(&self.post, &color, (traced.x, traced.y), frame);
+
+        let inside = Region::new(
+            region.x - traced.x,
+            region.y - traced.y,
+            region.width,
+            region.height,
+        );
+        buffers.truncate(aovs.len());
+        let buffers = buffers.iter().map(|buffer| buffer.crop(inside)).collect();
+        (color.crop(inside).quantize(), buffers)
+    }
+
+    fn pixel<R: Rng>(
+        &self,
+        x: usize,
+        y: usize,
+        aovs: &[Aov],
+        rng: &mut R,
+    ) -> (Radiance, Vec<(f64, f64, f64)>) {
+        let mut color = (0.0, 0.0, 0.0);
+        let mut values = vec![(0.0, 0.0, 0.0); aovs.len()];
+
+        for i in 0..self.samples {
+            let (dx, dy) = if self.samples > 1 {
+                (rng.gen::<f64>(), rng.gen::<f64>())
+            } else {
+                (0.0, 0.0)
+            };
+
+            let time = rng.gen::<f64>() * self.shutter;
+
+            let ray = self
+                .scene
+                .cam
+                .get_ray(x as f64 + dx, y as f64 + dy, time, rng);
+            let c = match ray {
+                Some(ray) if !aovs.is_empty() => {
+                    let mut sample = AovSample::miss();
+                    let c = self
+                        .shade(ray, Some(&mut sample))
+                        .unwrap_or_else(|| radiance(self.scene.background.sample(&ray.direction)));
+                    sample.color = c;
+
+                    let n = self.samples as f64;
+                    for (aov, value) in aovs.iter().zip(values.iter_mut()) {
+                        let (vr, vg, vb) = aov.value(&sample);
+                        if aov.is_averaged() {
+                            *value = (value.0 + vr / n, value.1 + vg / n, value.2 + vb / n);
+                        } else if i == 0 {
+                            *value = (vr, vg, vb);
+                        }
+                    }
+                    c
+                }
+                Some(ray) => self.trace(ray),
+                None => (0.0, 0.0, 0.0),
+            };
+            color = add(color, c);
+        }
+
+        let n = self.samples as f64;
+        ((color.0 / n, color.1 / n, color.2 / n), values)
     }
 
-    fn in_shadow(&self, obj: &Box<dyn Object>, pos: Point, light: &Box<dyn Light>) -> bool {
+    fn in_shadow(&self, obj: &dyn Object, pos: Point, light: &dyn Light, time: f64) -> bool {
         let light_ray = Ray {
             energy: 1.0,
+            time,
             origin: light.pos(),
             direction: Vector::from(light.pos(), pos).normalize(),
         };
@@ -163,8 +380,95 @@ impl Engine {
         false
     }
 
-    fn process_point(&self, pos: Point, obj: &Box<dyn Object>, ray: &Ray) -> Color {
-        let mut c = crate::common::BLACK;
+    /// Fraction of hemisphere rays around `normal` that travel further than
+    /// `radius` without hitting anything
+    fn ambient_visibility(
+        &self,
+        pos: Point,
+        normal: NormalVector,
+        samples: usize,
+        radius: f64,
+        time: f64,
+    ) -> f64 {
+        if samples == 0 {
+            return 1.0;
+        }
+
+        let mut rng = rand::thread_rng();
+        let epsilon = 0.05;
+        let origin = (Vector::from(ORIGIN, pos) + normal.vector() * epsilon).to_point();
+
+        let escaped = (0..samples)
+            .filter(|_| {
+                let ray = Ray {
+                    energy: 1.0,
+                    time,
+                    origin,
+                    direction: occlusion::sample_hemisphere(&normal, &mut rng),
+                };
+
+                !self
+                    .scene
+                    .objects
+                    .iter()
+                    .any(|obj| matches!(obj.intersects(ray), Some(d) if d < radius))
+            })
+            .count();
+
+        escaped as f64 / samples as f64
+    }
+
+    /// Average background radiance reaching `pos` from the hemisphere
+    /// around `normal`, ignoring rays blocked by an object
+    fn environment_irradiance(
+        &self,
+        pos: Point,
+        normal: NormalVector,
+        samples: usize,
+        time: f64,
+    ) -> (f64, f64, f64) {
+        if samples == 0 {
+            return (0.0, 0.0, 0.0);
+        }
+
+        let mut rng = rand::thread_rng();
+        let epsilon = 0.05;
+        let origin = (Vector::from(ORIGIN, pos) + normal.vector() * epsilon).to_point();
+
+        let (mut r, mut g, mut b) = (0.0, 0.0, 0.0);
+        for _ in 0..samples {
+            let ray = Ray {
+                energy: 1.0,
+                time,
+                origin,
+                direction: occlusion::sample_hemisphere(&normal, &mut rng),
+            };
+
+            if !self
+                .scene
+                .objects
+                .iter()
+                .any(|obj| obj.intersects(ray).is_some())
+            {
+                let Color(sr, sg, sb) = self.scene.background.sample(&ray.direction);
+                r += sr as f64 / 255.0;
+                g += sg as f64 / 255.0;
+                b += sb as f64 / 255.0;
+            }
+        }
+
+        let n = samples as f64;
+        (r / n, g / n, b / n)
+    }
+
+    fn process_point(
+        &self,
+        pos: Point,
+        obj: &dyn Object,
+        ray: &Ray,
+        mut aov: Option<&mut AovSample>,
+    ) -> Radiance {
+        let mut total = (0.0, 0.0, 0.0);
         let normal = obj.normal(pos);
 
         let normal = if NormalVector::dot_product(&normal, &ray.direction) > 0.0 {
@@ -177,12 +481,28 @@ impl Engine {
         let epsilon = 0.05;
         let epsilon_pos = (Vector::from(ORIGIN, pos) + reflected.vector() * epsilon).to_point();
 
+        // Occlusion does not depend on the lights so only sample it once
+        let visibility = self.mode.iter().find_map(|mode| match mode {
+            RenderingMode::AmbientOcclusion {
+                samples, radius, ..
+            } => Some(self.ambient_visibility(pos, normal, *samples, *radius, ray.time)),
+            _ => None,
+        });
+
+        if let Some(aov) = aov.as_deref_mut() {
+            let (r, g, b) = obj.diffusion(pos);
+            aov.normal = (normal.vector().x, normal.vector().y, normal.vector().z);
+            aov.albedo = (r / 255.0, g / 255.0, b / 255.0);
+            aov.lit = 1.0;
+        }
+
         for light in self.scene.lights.iter() {
-            let in_shadow = self.in_shadow(obj, pos, light);
+            let in_shadow = self.in_shadow(obj, pos, light.as_ref(), ray.time);
 
             let light_vector = Vector::from(pos, light.pos());
+            let mut light_radiance = (0.0, 0.0, 0.0);
             for mode in self.mode.iter() {
-                c += match mode {
+                let term = match mode {
                     RenderingMode::Intersect => intersection::process(obj.diffusion(pos)),
                     RenderingMode::Diffuse if !in_shadow => diffusion::process(
                         light_vector.normalize(),
@@ -199,6 +519,12 @@ impl Engine {
                     RenderingMode::Ambient(ambient_light) => {
                         ambient::process(light.intensity(), *ambient_light, obj.diffusion(pos))
                     }
+                    RenderingMode::AmbientOcclusion { ambient_light, .. } => occlusion::process(
+                        light.intensity(),
+                        *ambient_light,
+                        obj.diffusion(pos),
+                        visibility.unwrap_or(1.0),
+                    ),
                     RenderingMode::Reflection => {
                         let loss = 0.7;
                         let energy = ray.energy - loss;
@@ -206,33 +532,63 @@ impl Engine {
                         if energy > 0.0 {
                             let reflection_ray = Ray {
                                 energy,
+                                time: ray.time,
                                 origin: epsilon_pos,
                                 direction: reflected,
                             };
-                            if let Some(color) = self.cast_ray(reflection_ray) {
-                                color
-                            } else {
-                                crate::common::BLACK
-                            }
+                            self.trace(reflection_ray)
                         } else {
-                            crate::common::BLACK
+                            (0.0, 0.0, 0.0)
                         }
                     }
-                    _ => crate::common::BLACK,
+                    _ => (0.0, 0.0, 0.0),
                 };
+                light_radiance = add(light_radiance, term);
+            }
+            total = add(total, light_radiance);
+
+            if let Some(aov) = aov.as_deref_mut() {
+                aov.lights.push(light_radiance);
+                if in_shadow {
+                    aov.lit -= 1.0 / self.scene.lights.len() as f64;
+                }
             }
         }
 
-        c
+        for mode in self.mode.iter() {
+            if let RenderingMode::Environment { strength, samples } = mode {
+                let (r, g, b) = self.environment_irradiance(pos, normal, *samples, ray.time);
+                let term = ambient::process(
+                    (r, g, b),
+                    (*strength, *strength, *strength),
+                    obj.diffusion(pos),
+                );
+                total = add(total, term);
+            }
+        }
+
+        total
+    }
+
+    /// Cast a ray, falling back on the background if nothing is hit
+    pub fn trace(&self, ray: Ray) -> Radiance {
+        self.cast_ray(ray)
+            .unwrap_or_else(|| radiance(self.scene.background.sample(&ray.direction)))
     }
 
-    pub fn cast_ray(&self, ray: Ray) -> Option<Color> {
+    pub fn cast_ray(&self, ray: Ray) -> Option<Radiance> {
+        self.shade(ray, None)
+    }
+
+    /// Color of the closest object hit by the ray, filling `aov` with the
+    /// buffer values at that point
+    fn shade(&self, ray: Ray, mut aov: Option<&mut AovSample>) -> Option<Radiance> {
         // f64 is not hashable so we use u64 and convert f64 using to_bits
-        let mut intersections = HashMap::<u64, &Box<dyn Object>>::new();
+        let mut intersections = HashMap::<u64, (usize, &Box<dyn Object>)>::new();
 
-        for obj in self.scene.objects.iter() {
+        for (i, obj) in self.scene.objects.iter().enumerate() {
             if let Some(d) = obj.intersects(ray) {
-                intersections.insert(d.to_bits(), &obj);
+                intersections.insert(d.to_bits(), (i, obj));
             }
         }
 
@@ -248,8 +604,115 @@ impl Engine {
             + ray.direction.vector() * min)
             .to_point();
 
-        let closest: &Box<dyn Object> = intersections.get(&min.to_bits()).unwrap();
+        let (index, closest) = *intersections.get(&min.to_bits()).unwrap();
+        let surface = closest.surface(ray);
+
+        if let Some(aov) = aov.as_deref_mut() {
+            aov.depth = min;
+            aov.object = Some(index);
+        }
+
+        Some(self.process_point(
+            intersection_point,
+            surface.as_deref().unwrap_or(closest.as_ref()),
+            &ray,
+            aov,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{CameraTrack, Interpolation, Keyframe};
+
+    #[test]
+    fn motion_blur_keeps_fov() {
+        // The camera stays in place, only its field of view changes
+        let key = |time: f64, fov_angle: f64| Keyframe {
+            time,
+            pos: ORIGIN,
+            look_at: Point(0.0, 0.0, 8.0),
+            up: Vector::new(1.0, 0.0, 0.0).normalize(),
+            fov_angle: Some(fov_angle),
+        };
+        let track = CameraTrack::new(Interpolation::Linear)
+            .with_key(key(0.0, 45.0))
+            .with_key(key(1.0, 20.0));
+
+        let render = |shutter: f64| {
+            let mut engine = Engine::new(crate::premade_scenes::scene1::get(16, 16));
+            engine.set_intersect().set_motion_blur(shutter);
+
+            let mut frames = Vec::new();
+            let mut time = 0.0;
+            let mut move_cam = |cam: &mut Camera| {
+                time += 0.5;
+                cam.look(&track.sample(time))
+            };
+            engine.travelling(&mut move_cam, 3, &mut frames).unwrap();
+            frames
+        };
+
+        let (sharp, blurred) = (render(0.0), render(0.5));
+        for (a, b) in sharp.iter().zip(blurred.iter()) {
+            assert_eq!(a.to_rgb(), b.to_rgb());
+        }
+        assert_ne!(sharp[0].to_rgb(), sharp[2].to_rgb());
+    }
+
+    #[test]
+    fn color_buffer_is_unclamped() {
+        let mut engine = Engine::new(crate::premade_scenes::scene1::get(32, 32));
+        engine.set_ambient((4.0, 4.0, 4.0));
+
+        let (image, buffers) =
+            engine.render_aovs(Region::new(0, 0, 32, 32), &[Aov::Color, Aov::Light(0)]);
+        // A single light and shading mode goes over 1.0, the image being
+        // clamped only when quantized
+        assert!(buffers[1].values().any(|&(r, _, _)| r > 1.0));
+        assert_eq!(buffers[0].quantize().to_rgb(), image.to_rgb());
+    }
+
+    #[test]
+    fn tiles_match_whole_image() {
+        let mut engine = Engine::new(crate::premade_scenes::scene1::get(32, 32));
+        engine
+            .set_diffuse()
+            .set_specular()
+            .set_denoising(DenoiseOptions {
+                iterations: 2,
+                ..Default::default()
+            })
+            .set_post_processing(vec![
+                Effect::parse("bloom", &[0.8, 2.0, 1.0]).unwrap(),
+                Effect::Vignette(0.5),
+                Effect::Grain(0.1),
+            ]);
+
+        let whole = engine.render(None);
+        for region in Region::tiles(32, 32, 12) {
+            let tile = engine.render_cropped(region);
+            for y in 0..region.height {
+                for x in 0..region.width {
+                    assert_eq!(tile[y][x], whole[region.y + y][region.x + x]);
+                }
+            }
+        }
+    }
 
-        Some(self.process_point(intersection_point, closest, &ray))
+    #[test]
+    fn resolution() {
+        let mut engine = Engine::new(crate::premade_scenes::scene1::get(32, 32));
+        engine.set_diffuse().set_resolution(8, 8);
+        assert_eq!(engine.render(None).size(), (8, 8));
+
+        let full = Engine::new(crate::premade_scenes::scene1::get(32, 32))
+            .set_diffuse()
+            .render(None);
+        assert_eq!(
+            engine.set_resolution(32, 32).render(None).to_rgb(),
+            full.to_rgb()
+        );
     }
 }
@@ -1,12 +1,17 @@
 /// The main engine
 mod engine;
 
-/// The rendering methods
+/// The auxiliary buffers
+mod aov;
+
+/// The rendering methods, giving radiances where 1.0 is full white
 mod render {
     pub mod ambient;
     pub mod diffusion;
     pub mod intersection;
+    pub mod occlusion;
     pub mod specularity;
 }
 
+pub use aov::Aov;
 pub use engine::Engine;
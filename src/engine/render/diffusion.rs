@@ -5,7 +5,7 @@ pub fn process(
     light_intensity: (f64, f64, f64),
     diffusion: (f64, f64, f64),
     normal: NormalVector,
-) -> Color {
+) -> (f64, f64, f64) {
     // I = k * (N.L) * I_l
     let (kr, kg, kb) = diffusion;
     let (lr, lg, lb) = light_intensity;
@@ -13,12 +13,12 @@ pub fn process(
     let proportion = NormalVector::dot_product(&normal, &light_vector);
 
     if proportion < 0.0 {
-        return crate::common::BLACK;
+        return (0.0, 0.0, 0.0);
     }
 
-    let ir = kr * proportion * lr;
-    let ig = kg * proportion * lg;
-    let ib = kb * proportion * lb;
-
-    Color(ir as u8, ig as u8, ib as u8)
+    (
+        kr * proportion * lr / 255.0,
+        kg * proportion * lg / 255.0,
+        kb * proportion * lb / 255.0,
+    )
 }
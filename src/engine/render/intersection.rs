@@ -1,6 +1,4 @@
-use crate::common::*;
-
-pub fn process(diffusion: (f64, f64, f64)) -> Color {
+pub fn process(diffusion: (f64, f64, f64)) -> (f64, f64, f64) {
     let (r, g, b) = diffusion;
-    Color(r as u8, g as u8, b as u8)
+    (r / 255.0, g / 255.0, b / 255.0)
 }
@@ -0,0 +1,39 @@
+use rand::Rng;
+
+use crate::common::*;
+
+use super::ambient;
+
+/// Pick a cosine-weighted random direction in the hemisphere around `normal`
+pub fn sample_hemisphere<R: Rng>(normal: &NormalVector, rng: &mut R) -> NormalVector {
+    let n = normal.vector();
+
+    // Build an orthonormal basis around the normal
+    let helper = if n.x.abs() > 0.9 {
+        Vector::new(0.0, 1.0, 0.0)
+    } else {
+        Vector::new(1.0, 0.0, 0.0)
+    };
+    let t = Vector::cross_product(&n, &helper).normalize().vector();
+    let b = Vector::cross_product(&n, &t);
+
+    let (u1, u2) = (rng.gen::<f64>(), rng.gen::<f64>());
+    let r = u1.sqrt();
+    let phi = 2.0 * std::f64::consts::PI * u2;
+
+    (t * (r * phi.cos()) + b * (r * phi.sin()) + n * (1.0 - u1).sqrt()).normalize()
+}
+
+pub fn process(
+    light_intensity: (f64, f64, f64),
+    ambient_light: (f64, f64, f64),
+    diffusion: (f64, f64, f64),
+    visibility: f64,
+) -> (f64, f64, f64) {
+    let (ar, ag, ab) = ambient_light;
+    ambient::process(
+        light_intensity,
+        (ar * visibility, ag * visibility, ab * visibility),
+        diffusion,
+    )
+}
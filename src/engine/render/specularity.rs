@@ -5,7 +5,7 @@ pub fn process(
     light_intensity: (f64, f64, f64),
     reflection: f64,
     reflected: NormalVector,
-) -> Color {
+) -> (f64, f64, f64) {
     // I = k * (S.L)^ns * I_l
     let (lr, lg, lb) = light_intensity;
     let mean_intensity = lr / 3.0 + lg / 3.0 + lb / 3.0;
@@ -13,10 +13,10 @@ pub fn process(
     let ns = 3.0;
     let dot = NormalVector::dot_product(&light_vector, &reflected);
     if dot < 0.0 {
-        return Color(0, 0, 0);
+        return (0.0, 0.0, 0.0);
     }
 
-    let i = (reflection * dot.powf(ns) * mean_intensity * (u8::MAX as f64)) as u8;
+    let i = reflection * dot.powf(ns) * mean_intensity;
 
-    Color(i, i, i)
+    (i, i, i)
 }
@@ -0,0 +1,360 @@
+//! Export of L-System geometry to mesh formats read by other tools
+
+use std::f64::consts::PI;
+use std::io::prelude::*;
+use std::{fs::File, io::BufWriter, path::Path};
+
+use crate::common::*;
+use crate::lsystem::{LSGeometry, LSMaterial, LSPrimitive};
+
+/// Polygon soup where every vertex carries its own normal and uv
+#[derive(Default)]
+struct Tessellation {
+    vertices: Vec<Point>,
+    normals: Vec<Vector>,
+    uvs: Vec<(f64, f64)>,
+    // Material index and vertex indices
+    faces: Vec<(usize, Vec<usize>)>,
+}
+
+/// Two unit vectors orthogonal to `axis` and to each other
+fn basis(axis: &NormalVector) -> (Vector, Vector) {
+    let n = axis.vector();
+    let helper = if n.x.abs() > 0.9 {
+        Vector::new(0.0, 1.0, 0.0)
+    } else {
+        Vector::new(1.0, 0.0, 0.0)
+    };
+    let u = Vector::cross_product(&n, &helper).normalize().vector();
+    let v = Vector::cross_product(&n, &u);
+    (u, v)
+}
+
+fn offset(p: Point, v: Vector) -> Point {
+    (Vector::from(ORIGIN, p) + v).to_point()
+}
+
+impl Tessellation {
+    fn push_vertex(&mut self, p: Point, normal: Vector, uv: (f64, f64)) -> usize {
+        self.vertices.push(p);
+        self.normals.push(normal);
+        self.uvs.push(uv);
+        self.vertices.len() - 1
+    }
+
+    fn add_edge(&mut self, material: usize, a: Point, b: Point, ra: f64, rb: f64, sides: usize) {
+        let axis = Vector::from(a, b);
+        let length = axis.norm();
+        if length < f64::EPSILON {
+            return;
+        }
+        let direction = axis.normalize();
+        let (u, v) = basis(&direction);
+        let slope = (rb - ra) / length;
+
+        // Side, with a duplicated seam so the texture wraps around
+        let start = self.vertices.len();
+        for i in 0..=sides {
+            let angle = 2.0 * PI * i as f64 / sides as f64;
+            let radial = u * angle.cos() + v * angle.sin();
+            let normal = (radial - direction.vector() * slope).normalize().vector();
+            let s = i as f64 / sides as f64;
+
+            self.push_vertex(offset(a, radial * ra), normal, (s, 0.0));
+            self.push_vertex(offset(b, radial * rb), normal, (s, 1.0));
+        }
+        for i in 0..sides {
+            let (a0, b0) = (start + 2 * i, start + 2 * i + 1);
+            let (a1, b1) = (a0 + 2, b0 + 2);
+            self.faces.push((material, vec![a0, a1, b1, b0]));
+        }
+
+        // Caps
+        for &(center, r, normal) in [(a, ra, -direction), (b, rb, direction)].iter() {
+            let ring: Vec<usize> = (0..sides)
+                .map(|i| {
+                    let angle = 2.0 * PI * i as f64 / sides as f64;
+                    let radial = u * angle.cos() + v * angle.sin();
+                    self.push_vertex(
+                        offset(center, radial * r),
+                        normal.vector(),
+                        (0.5 + angle.cos() / 2.0, 0.5 + angle.sin() / 2.0),
+                    )
+                })
+                .collect();
+
+            let ring = if NormalVector::dot_product(&normal, &direction) > 0.0 {
+                ring
+            } else {
+                ring.into_iter().rev().collect()
+            };
+            self.faces.push((material, ring));
+        }
+    }
+
+    fn add_sphere(&mut self, material: usize, center: Point, radius: f64, sides: usize) {
+        let rings = (sides / 2).max(2);
+        let start = self.vertices.len();
+
+        for j in 0..=rings {
+            let theta = PI * j as f64 / rings as f64;
+            for i in 0..=sides {
+                let phi = 2.0 * PI * i as f64 / sides as f64;
+                let normal = Vector::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                self.push_vertex(
+                    offset(center, normal * radius),
+                    normal,
+                    (i as f64 / sides as f64, j as f64 / rings as f64),
+                );
+            }
+        }
+
+        let index = |i: usize, j: usize| start + j * (sides + 1) + i;
+        for j in 0..rings {
+            for i in 0..sides {
+                self.faces.push((
+                    material,
+                    vec![
+                        index(i, j),
+                        index(i, j + 1),
+                        index(i + 1, j + 1),
+                        index(i + 1, j),
+                    ],
+                ));
+            }
+        }
+    }
+
+    fn add_leaf(&mut self, material: usize, points: &[Point]) {
+        let normal = Vector::cross_product(
+            &Vector::from(points[0], points[1]),
+            &Vector::from(points[0], points[2]),
+        );
+        let normal = if normal.norm() > f64::EPSILON {
+            normal.normalize().vector()
+        } else {
+            Vector::new(0.0, 0.0, 1.0)
+        };
+
+        let face = points
+            .iter()
+            .map(|&p| self.push_vertex(p, normal, (0.0, 0.0)))
+            .collect();
+        self.faces.push((material, face));
+    }
+
+    fn new(geometry: &LSGeometry, sides: usize) -> Tessellation {
+        let sides = sides.max(3);
+        let mut res = Tessellation::default();
+
+        for shape in geometry.shapes.iter() {
+            match &shape.primitive {
+                LSPrimitive::Edge { a, b, ra, rb } => {
+                    res.add_edge(shape.material, *a, *b, *ra, *rb, sides)
+                }
+                LSPrimitive::Joint { center, radius } | LSPrimitive::Fruit { center, radius } => {
+                    res.add_sphere(shape.material, *center, *radius, sides)
+                }
+                LSPrimitive::Leaf { points } => res.add_leaf(shape.material, points),
+            }
+        }
+
+        res
+    }
+}
+
+fn material_name(index: usize) -> String {
+    format!("material{}", index)
+}
+
+/// Representative color of a material, used where textures are unsupported
+fn material_color(material: &LSMaterial) -> Color {
+    match material {
+        LSMaterial::Uniform(c) => *c,
+        LSMaterial::Texture(path) => match imagelib::open(path) {
+            Ok(image) => {
+                let image = image.to_rgb8();
+                let n = (image.width() * image.height()).max(1) as u64;
+                let (r, g, b) = image.pixels().fold((0, 0, 0), |(r, g, b), p| {
+                    (r + p.0[0] as u64, g + p.0[1] as u64, b + p.0[2] as u64)
+                });
+                Color((r / n) as u8, (g / n) as u8, (b / n) as u8)
+            }
+            Err(_) => Color(200, 200, 200),
+        },
+    }
+}
+
+fn write_mtl(geometry: &LSGeometry, path: &Path) -> std::io::Result<()> {
+    let mut stream = BufWriter::new(File::create(path)?);
+
+    for (i, material) in geometry.materials.iter().enumerate() {
+        writeln!(stream, "newmtl {}", material_name(i))?;
+        match material {
+            LSMaterial::Uniform(Color(r, g, b)) => writeln!(
+                stream,
+                "Kd {} {} {}",
+                *r as f64 / 255.0,
+                *g as f64 / 255.0,
+                *b as f64 / 255.0
+            )?,
+            LSMaterial::Texture(texture) => {
+                let texture = std::fs::canonicalize(texture)
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .unwrap_or_else(|_| texture.clone());
+                writeln!(stream, "Kd 1 1 1")?;
+                writeln!(stream, "map_Kd {}", texture)?
+            }
+        }
+        writeln!(stream)?;
+    }
+
+    stream.flush()
+}
+
+/// Write the geometry as a Wavefront OBJ file, along with its MTL file,
+/// tessellating round shapes with `sides` sides
+pub fn to_obj(geometry: &LSGeometry, path: &str, sides: usize) -> std::io::Result<()> {
+    let path = Path::new(path);
+    let mtl_path = path.with_extension("mtl");
+    write_mtl(geometry, &mtl_path)?;
+
+    let mesh = Tessellation::new(geometry, sides);
+    let mut stream = BufWriter::new(File::create(path)?);
+
+    writeln!(
+        stream,
+        "mtllib {}",
+        mtl_path.file_name().unwrap().to_string_lossy()
+    )?;
+
+    for Point(x, y, z) in mesh.vertices.iter() {
+        writeln!(stream, "v {} {} {}", x, y, z)?;
+    }
+    for n in mesh.normals.iter() {
+        writeln!(stream, "vn {} {} {}", n.x, n.y, n.z)?;
+    }
+    for (u, v) in mesh.uvs.iter() {
+        writeln!(stream, "vt {} {}", u, v)?;
+    }
+
+    let mut current = None;
+    for (material, face) in mesh.faces.iter() {
+        if current != Some(*material) {
+            writeln!(stream, "usemtl {}", material_name(*material))?;
+            current = Some(*material);
+        }
+
+        write!(stream, "f")?;
+        for i in face.iter() {
+            write!(stream, " {0}/{0}/{0}", i + 1)?;
+        }
+        writeln!(stream)?;
+    }
+
+    stream.flush()
+}
+
+/// Write the geometry as an ASCII PLY file with per vertex colors
+pub fn to_ply(geometry: &LSGeometry, path: &str, sides: usize) -> std::io::Result<()> {
+    let mesh = Tessellation::new(geometry, sides);
+    let colors: Vec<Color> = geometry.materials.iter().map(material_color).collect();
+
+    // Vertices are never shared between faces so each has a single material
+    let mut vertex_color = vec![Color(0, 0, 0); mesh.vertices.len()];
+    for (material, face) in mesh.faces.iter() {
+        for &i in face.iter() {
+            vertex_color[i] = colors[*material];
+        }
+    }
+
+    let mut stream = BufWriter::new(File::create(path)?);
+
+    writeln!(stream, "ply")?;
+    writeln!(stream, "format ascii 1.0")?;
+    writeln!(stream, "element vertex {}", mesh.vertices.len())?;
+    for property in ["x", "y", "z", "nx", "ny", "nz"].iter() {
+        writeln!(stream, "property float {}", property)?;
+    }
+    for property in ["red", "green", "blue"].iter() {
+        writeln!(stream, "property uchar {}", property)?;
+    }
+    writeln!(stream, "element face {}", mesh.faces.len())?;
+    writeln!(stream, "property list uchar int vertex_indices")?;
+    writeln!(stream, "end_header")?;
+
+    for (i, Point(x, y, z)) in mesh.vertices.iter().enumerate() {
+        let n = mesh.normals[i];
+        let Color(r, g, b) = vertex_color[i];
+        writeln!(
+            stream,
+            "{} {} {} {} {} {} {} {} {}",
+            x, y, z, n.x, n.y, n.z, r, g, b
+        )?;
+    }
+
+    for (_, face) in mesh.faces.iter() {
+        write!(stream, "{}", face.len())?;
+        for i in face.iter() {
+            write!(stream, " {}", i)?;
+        }
+        writeln!(stream)?;
+    }
+
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsystem::LSShape;
+    use crate::scene::{obj, Ray};
+
+    use super::*;
+
+    #[test]
+    fn obj_round_trip() {
+        let geometry = LSGeometry {
+            shapes: vec![
+                LSShape {
+                    primitive: LSPrimitive::Edge {
+                        a: Point(0.0, 0.0, 5.0),
+                        b: Point(0.0, 2.0, 5.0),
+                        ra: 1.0,
+                        rb: 0.5,
+                    },
+                    material: 0,
+                },
+                LSShape {
+                    primitive: LSPrimitive::Fruit {
+                        center: Point(5.0, 0.0, 5.0),
+                        radius: 1.0,
+                    },
+                    material: 1,
+                },
+            ],
+            materials: vec![LSMaterial::Uniform(RED), LSMaterial::Uniform(GREEN)],
+        };
+
+        let path = std::env::temp_dir().join("isim_export_round_trip.obj");
+        to_obj(&geometry, path.to_str().unwrap(), 16).unwrap();
+
+        let objs = obj::PropCache::default()
+            .load(path.to_str().unwrap())
+            .unwrap();
+        assert_eq!(objs.len(), 2);
+
+        let ray = Ray {
+            energy: 1.0,
+            time: 0.0,
+            origin: Point(0.0, 1.0, 0.0),
+            direction: Vector::new(0.0, 0.0, 1.0).normalize(),
+        };
+        let d = objs[0].intersects(ray).unwrap();
+        assert!((d - 4.25).abs() < 0.05);
+        assert!(objs[1].intersects(ray).is_none());
+    }
+}
@@ -0,0 +1,159 @@
+use crate::common::Point;
+
+use super::{NormalVector, Vector};
+
+/// Axis aligned bounding box
+#[derive(Copy, Clone, Debug)]
+pub struct BoundingBox {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl BoundingBox {
+    /// Box containing nothing, neutral element of `union`
+    pub fn empty() -> BoundingBox {
+        BoundingBox {
+            min: Point(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Point(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points(points: &[Point]) -> BoundingBox {
+        points
+            .iter()
+            .fold(BoundingBox::empty(), |acc, &p| acc.extend(p))
+    }
+
+    pub fn extend(self, p: Point) -> BoundingBox {
+        BoundingBox {
+            min: Point(
+                self.min.0.min(p.0),
+                self.min.1.min(p.1),
+                self.min.2.min(p.2),
+            ),
+            max: Point(
+                self.max.0.max(p.0),
+                self.max.1.max(p.1),
+                self.max.2.max(p.2),
+            ),
+        }
+    }
+
+    pub fn union(self, other: BoundingBox) -> BoundingBox {
+        self.extend(other.min).extend(other.max)
+    }
+
+    /// Grow the box by `r` in every direction
+    pub fn expand(self, r: f64) -> BoundingBox {
+        BoundingBox {
+            min: Point(self.min.0 - r, self.min.1 - r, self.min.2 - r),
+            max: Point(self.max.0 + r, self.max.1 + r, self.max.2 + r),
+        }
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        (self.min.0..=self.max.0).contains(&p.0)
+            && (self.min.1..=self.max.1).contains(&p.1)
+            && (self.min.2..=self.max.2).contains(&p.2)
+    }
+
+    /// The 8 corners of the box
+    pub fn corners(&self) -> [Point; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Point(a.0, a.1, a.2),
+            Point(a.0, a.1, b.2),
+            Point(a.0, b.1, a.2),
+            Point(a.0, b.1, b.2),
+            Point(b.0, a.1, a.2),
+            Point(b.0, a.1, b.2),
+            Point(b.0, b.1, a.2),
+            Point(b.0, b.1, b.2),
+        ]
+    }
+
+    pub fn size(&self) -> Vector {
+        Vector::from(self.min, self.max)
+    }
+
+    /// Index of the axis along which the box is the widest
+    pub fn longest_axis(&self) -> usize {
+        let size = self.size();
+        if size.x >= size.y && size.x >= size.z {
+            0
+        } else if size.y >= size.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Slab test, returns the entry and exit distances along the ray
+    pub fn intersects(&self, origin: Point, direction: &NormalVector) -> Option<(f64, f64)> {
+        let d = direction.vector();
+        let axes = [
+            (origin.0, d.x, self.min.0, self.max.0),
+            (origin.1, d.y, self.min.1, self.max.1),
+            (origin.2, d.z, self.min.2, self.max.2),
+        ];
+
+        let mut near = f64::NEG_INFINITY;
+        let mut far = f64::INFINITY;
+
+        for &(o, d, min, max) in axes.iter() {
+            if d.abs() < f64::EPSILON {
+                if o < min || o > max {
+                    return None;
+                }
+                continue;
+            }
+
+            let (t1, t2) = ((min - o) / d, (max - o) / d);
+            let (t1, t2) = if t1 < t2 { (t1, t2) } else { (t2, t1) };
+
+            near = near.max(t1);
+            far = far.min(t2);
+
+            if near > far {
+                return None;
+            }
+        }
+
+        if far < 0.0 {
+            None
+        } else {
+            Some((near, far))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intersects_true() {
+        let b = BoundingBox {
+            min: Point(-1.0, -1.0, 4.0),
+            max: Point(1.0, 1.0, 6.0),
+        };
+        let hit = b.intersects(
+            Point(0.0, 0.0, 0.0),
+            &Vector::new(0.0, 0.0, 1.0).normalize(),
+        );
+        assert_eq!(hit, Some((4.0, 6.0)))
+    }
+
+    #[test]
+    fn intersects_false() {
+        let b = BoundingBox {
+            min: Point(-1.0, -1.0, 4.0),
+            max: Point(1.0, 1.0, 6.0),
+        };
+        let hit = b.intersects(
+            Point(0.0, 0.0, 0.0),
+            &Vector::new(1.0, 0.0, 1.0).normalize(),
+        );
+        assert!(hit.is_none())
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Color(pub u8, pub u8, pub u8);
 
 fn add_color(a: u8, b: u8) -> u8 {
@@ -0,0 +1,176 @@
+use std::ops;
+
+use crate::common::Point;
+
+use super::{NormalVector, Vector};
+
+/// 4x4 matrix of an affine transform, applied to column vectors
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Matrix(pub [[f64; 4]; 4]);
+
+/// Standard matrix constructors
+impl Matrix {
+    pub fn identity() -> Matrix {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Matrix(m)
+    }
+
+    pub fn translation(v: Vector) -> Matrix {
+        let mut m = Matrix::identity();
+        m.0[0][3] = v.x;
+        m.0[1][3] = v.y;
+        m.0[2][3] = v.z;
+        m
+    }
+
+    pub fn scaling(x: f64, y: f64, z: f64) -> Matrix {
+        let mut m = Matrix::identity();
+        m.0[0][0] = x;
+        m.0[1][1] = y;
+        m.0[2][2] = z;
+        m
+    }
+
+    /// Rotation of `angle` radians around `axis`, going through the origin
+    pub fn rotation(axis: &NormalVector, angle: f64) -> Matrix {
+        let columns = [
+            Vector::new(1.0, 0.0, 0.0).rotate(axis, angle),
+            Vector::new(0.0, 1.0, 0.0).rotate(axis, angle),
+            Vector::new(0.0, 0.0, 1.0).rotate(axis, angle),
+        ];
+
+        let mut m = Matrix::identity();
+        for (j, c) in columns.iter().enumerate() {
+            m.0[0][j] = c.x;
+            m.0[1][j] = c.y;
+            m.0[2][j] = c.z;
+        }
+        m
+    }
+}
+
+impl Matrix {
+    pub fn transform_point(&self, p: Point) -> Point {
+        let m = &self.0;
+        Point(
+            m[0][0] * p.0 + m[0][1] * p.1 + m[0][2] * p.2 + m[0][3],
+            m[1][0] * p.0 + m[1][1] * p.1 + m[1][2] * p.2 + m[1][3],
+            m[2][0] * p.0 + m[2][1] * p.1 + m[2][2] * p.2 + m[2][3],
+        )
+    }
+
+    /// Transform a direction, ignoring the translation
+    pub fn transform_vector(&self, v: Vector) -> Vector {
+        let m = &self.0;
+        Vector::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = self.0[j][i];
+            }
+        }
+        Matrix(m)
+    }
+
+    /// Gauss-Jordan elimination, None if the matrix is singular
+    pub fn inverse(&self) -> Option<Matrix> {
+        let mut m = self.0;
+        let mut inv = Matrix::identity().0;
+
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&a, &b| {
+                m[a][col]
+                    .abs()
+                    .partial_cmp(&m[b][col].abs())
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })?;
+
+            if m[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+
+            m.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let factor = m[col][col];
+            for j in 0..4 {
+                m[col][j] /= factor;
+                inv[col][j] /= factor;
+            }
+
+            for row in 0..4 {
+                if row != col {
+                    let factor = m[row][col];
+                    for j in 0..4 {
+                        m[row][j] -= factor * m[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+
+        Some(Matrix(inv))
+    }
+}
+
+impl ops::Mul<Matrix> for Matrix {
+    type Output = Matrix;
+    fn mul(self, rhs: Matrix) -> Matrix {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = (0..4).map(|k| self.0[i][k] * rhs.0[k][j]).sum();
+            }
+        }
+        Matrix(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &Matrix, b: &Matrix) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((a.0[i][j] - b.0[i][j]).abs() < 1e-9, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_inverse() {
+        let m = Matrix::translation(Vector::new(1.0, 2.0, 3.0))
+            * Matrix::rotation(&Vector::new(1.0, 1.0, 0.0).normalize(), 0.7)
+            * Matrix::scaling(2.0, 0.5, 3.0);
+
+        assert_close(&(m * m.inverse().unwrap()), &Matrix::identity());
+    }
+
+    #[test]
+    fn test_singular() {
+        assert!(Matrix::scaling(1.0, 0.0, 1.0).inverse().is_none())
+    }
+
+    #[test]
+    fn test_transform() {
+        let m = Matrix::translation(Vector::new(0.0, 0.0, 5.0))
+            * Matrix::rotation(&Vector::new(0.0, 0.0, 1.0).normalize(), 90f64.to_radians());
+
+        let Point(x, y, z) = m.transform_point(Point(1.0, 0.0, 0.0));
+        assert!(x.abs() < 1e-9 && (y - 1.0).abs() < 1e-9 && (z - 5.0).abs() < 1e-9);
+
+        let v = m.transform_vector(Vector::new(1.0, 0.0, 0.0));
+        assert!(v.x.abs() < 1e-9 && (v.y - 1.0).abs() < 1e-9 && v.z.abs() < 1e-9);
+    }
+}
@@ -1,8 +1,12 @@
+mod bounding_box;
 mod color;
+mod matrix;
 mod point;
 mod vector;
 
+pub use bounding_box::BoundingBox;
 pub use color::Color;
+pub use matrix::Matrix;
 pub use point::Point;
 pub use vector::NormalVector;
 pub use vector::Vector;
@@ -13,7 +13,7 @@ pub struct Image {
 
 /// Constructors and getters/setters
 impl Image {
-    pub fn new(height: usize, width: usize) -> Image {
+    pub fn new(width: usize, height: usize) -> Image {
         Image {
             grid: vec![Color(0, 0, 0); height * width],
             height,
@@ -21,13 +21,269 @@ impl Image {
         }
     }
 
+    /// `x` is the column and `y` the row, from the top left corner
     pub fn set(&mut self, x: usize, y: usize, color: Color) {
-        if x >= self.height {
+        if x >= self.width {
             panic!("Invalid x coordinate: {}", x);
-        } else if y >= self.width {
+        } else if y >= self.height {
             panic!("Invalid y coordinate: {}", y);
         }
-        self.grid[x * self.width + y] = color
+        self.grid[y * self.width + x] = color
+    }
+
+    /// Width and height in pixels
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+}
+
+/// Image of floating point RGB values, for buffers that do not fit in 8 bits
+/// per channel
+#[derive(Debug, Clone)]
+pub struct FloatImage {
+    grid: Vec<(f64, f64, f64)>,
+    height: usize,
+    width: usize,
+}
+
+impl FloatImage {
+    pub fn new(width: usize, height: usize) -> FloatImage {
+        FloatImage {
+            grid: vec![(0.0, 0.0, 0.0); height * width],
+            height,
+            width,
+        }
+    }
+
+    /// `x` is the column and `y` the row, from the top left corner
+    pub fn set(&mut self, x: usize, y: usize, value: (f64, f64, f64)) {
+        assert!(x < self.width && y < self.height);
+        self.grid[y * self.width + x] = value
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> (f64, f64, f64) {
+        self.grid[y * self.width + x]
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &(f64, f64, f64)> {
+        self.grid.iter()
+    }
+
+    /// Copy of the pixels of `region`, which must lie inside the image
+    pub fn crop(&self, region: Region) -> FloatImage {
+        let mut res = FloatImage::new(region.width, region.height);
+        for y in 0..region.height {
+            for x in 0..region.width {
+                res.set(x, y, self.get(region.x + x, region.y + y));
+            }
+        }
+        res
+    }
+
+    /// Image of the values clamped between 0 and 1
+    pub fn quantize(&self) -> Image {
+        let channel = |v: f64| (v.clamp(0.0, 1.0) * 255.0) as u8;
+        self.to_image(|(r, g, b)| Color(channel(r), channel(g), channel(b)))
+    }
+
+    /// Image whose pixels are the values converted by `to_color`
+    pub fn to_image<F: Fn((f64, f64, f64)) -> Color>(&self, to_color: F) -> Image {
+        Image {
+            grid: self.grid.iter().map(|&v| to_color(v)).collect(),
+            height: self.height,
+            width: self.width,
+        }
+    }
+}
+
+/// Most denoiser passes, the last one already reaching tens of thousands of
+/// pixels away
+pub const MAX_DENOISE_ITERATIONS: usize = 16;
+
+/// Settings of the denoiser, the sigmas setting how different two pixels
+/// can be in each buffer before they are no longer blended
+#[derive(Debug, Clone, Copy)]
+pub struct DenoiseOptions {
+    /// Number of passes, each one reaching twice as far as the previous one,
+    /// at most `MAX_DENOISE_ITERATIONS`
+    pub iterations: usize,
+    pub color_sigma: f64,
+    pub normal_sigma: f64,
+    pub albedo_sigma: f64,
+}
+
+impl DenoiseOptions {
+    /// Distance in pixels up to which the filter reads around a pixel
+    pub fn reach(&self) -> usize {
+        2 * ((1 << self.iterations) - 1)
+    }
+}
+
+impl Default for DenoiseOptions {
+    fn default() -> Self {
+        DenoiseOptions {
+            iterations: 4,
+            color_sigma: 0.5,
+            normal_sigma: 0.3,
+            albedo_sigma: 0.1,
+        }
+    }
+}
+
+fn distance2(a: (f64, f64, f64), b: (f64, f64, f64)) -> f64 {
+    (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2) + (a.2 - b.2).powi(2)
+}
+
+/// Smooth the noise of `color` with an edge-avoiding à-trous wavelet filter,
+/// pixels being blended only where the `normal` and `albedo` buffers show the
+/// same surface
+pub fn denoise(
+    color: &FloatImage,
+    normal: &FloatImage,
+    albedo: &FloatImage,
+    options: &DenoiseOptions,
+) -> FloatImage {
+    // B3 spline, spread further apart at each iteration
+    const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+    let (width, height) = (color.width, color.height);
+    let mut res = color.clone();
+
+    for i in 0..options.iterations {
+        let step = 1 << i;
+        // Later passes see smoother colors so they tolerate less difference
+        let color_sigma = options.color_sigma / (1 << i) as f64;
+        let input = res.clone();
+
+        for y in 0..height {
+            for x in 0..width {
+                let (c, n, a) = (input.get(x, y), normal.get(x, y), albedo.get(x, y));
+                let (mut sum, mut total) = ((0.0, 0.0, 0.0), 0.0);
+
+                for (v, ky) in KERNEL.iter().enumerate() {
+                    for (u, kx) in KERNEL.iter().enumerate() {
+                        let qx = x as isize + (u as isize - 2) * step;
+                        let qy = y as isize + (v as isize - 2) * step;
+                        if qx < 0 || qy < 0 || qx as usize >= width || qy as usize >= height {
+                            continue;
+                        }
+                        let (qx, qy) = (qx as usize, qy as usize);
+
+                        let qc = input.get(qx, qy);
+                        let weight = kx
+                            * ky
+                            * (-distance2(c, qc) / color_sigma.powi(2)).exp()
+                            * (-distance2(n, normal.get(qx, qy)) / options.normal_sigma.powi(2))
+                                .exp()
+                            * (-distance2(a, albedo.get(qx, qy)) / options.albedo_sigma.powi(2))
+                                .exp();
+
+                        sum = (
+                            sum.0 + qc.0 * weight,
+                            sum.1 + qc.1 * weight,
+                            sum.2 + qc.2 * weight,
+                        );
+                        total += weight;
+                    }
+                }
+
+                // The center pixel always has a positive weight
+                res.set(x, y, (sum.0 / total, sum.1 / total, sum.2 / total));
+            }
+        }
+    }
+
+    res
+}
+
+/// Rectangle of pixels whose top left corner is at column `x` and row `y`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Region {
+        Region {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Part of the region lying inside a `width` x `height` image
+    pub fn clip(&self, width: usize, height: usize) -> Region {
+        let (x, y) = (self.x.min(width), self.y.min(height));
+        Region {
+            x,
+            y,
+            width: self.width.min(width - x),
+            height: self.height.min(height - y),
+        }
+    }
+
+    /// Region extended by `margin` pixels on every side, stopping at the top
+    /// left corner
+    pub fn grow(&self, margin: usize) -> Region {
+        let (x, y) = (self.x.saturating_sub(margin), self.y.saturating_sub(margin));
+        Region {
+            x,
+            y,
+            width: self.x - x + self.width + margin,
+            height: self.y - y + self.height + margin,
+        }
+    }
+
+    /// Regions of at most `size` x `size` pixels covering a `width` x `height`
+    /// image, row by row
+    pub fn tiles(width: usize, height: usize, size: usize) -> Vec<Region> {
+        let size = size.max(1);
+        (0..height)
+            .step_by(size)
+            .flat_map(|y| {
+                (0..width)
+                    .step_by(size)
+                    .map(move |x| Region::new(x, y, size, size).clip(width, height))
+            })
+            .collect()
+    }
+}
+
+/// Composition of images
+impl Image {
+    /// Copy `image` with its top left corner at column `x` and row `y`,
+    /// dropping what falls outside
+    pub fn paste(&mut self, image: &Image, x: usize, y: usize) {
+        let region = Region::new(x, y, image.width, image.height).clip(self.width, self.height);
+        for j in 0..region.height {
+            for i in 0..region.width {
+                self.set(x + i, y + j, image[j][i]);
+            }
+        }
+    }
+
+    /// Lay out same-sized images in a grid of `columns` columns, row by row
+    pub fn tile(images: &[Image], columns: usize) -> Image {
+        assert!(!images.is_empty() && columns > 0);
+
+        let (width, height) = (images[0].width, images[0].height);
+        let rows = images.len().div_ceil(columns);
+        let mut res = Image::new(width * columns, height * rows);
+
+        for (i, image) in images.iter().enumerate() {
+            assert!(image.width == width && image.height == height);
+            res.paste(image, (i % columns) * width, (i / columns) * height);
+        }
+
+        res
     }
 }
 
@@ -73,35 +329,206 @@ impl Image {
         stream.flush()
     }
 
-    fn to_gif_frame(&self) -> gif::Frame {
-        let mut buf = Vec::new();
-        self.grid.iter().for_each(|&Color(r, g, b)| {
-            buf.push(r);
-            buf.push(g);
-            buf.push(b)
-        });
+    /// Pixels as packed 8-bit RGB, row by row
+    pub fn to_rgb(&self) -> Vec<u8> {
+        self.grid
+            .iter()
+            .flat_map(|&Color(r, g, b)| vec![r, g, b])
+            .collect()
+    }
+
+    /// Write the raw RGB pixels, as expected by video encoders reading
+    /// `rgb24` frames
+    pub fn write_rgb<W: Write>(&self, stream: &mut W) -> std::io::Result<()> {
+        stream.write_all(&self.to_rgb())
+    }
+
+    pub fn to_png<W: Write>(&self, stream: W) -> std::io::Result<()> {
+        let mut encoder = png::Encoder::new(stream, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.to_rgb())?;
+        Ok(writer.finish()?)
+    }
+
+    pub fn to_gif_frame(&self, options: &GifOptions) -> gif::Frame<'_> {
+        let (width, height) = (self.width as u16, self.height as u16);
+
+        let mut frame = if options.dither {
+            self.to_dithered_gif_frame(options.speed)
+        } else {
+            gif::Frame::from_rgb_speed(width, height, &self.to_rgb(), options.speed)
+        };
+
+        frame.delay = options.delay();
+        frame
+    }
+
+    /// Quantize the image with Floyd-Steinberg error diffusion
+    fn to_dithered_gif_frame(&self, speed: i32) -> gif::Frame<'_> {
+        let rgba: Vec<u8> = self
+            .grid
+            .iter()
+            .flat_map(|&Color(r, g, b)| vec![r, g, b, 255])
+            .collect();
+        let quant = color_quant::NeuQuant::new(speed, 256, &rgba);
+        let palette = quant.color_map_rgb();
+
+        let mut error = vec![(0.0, 0.0, 0.0); self.grid.len()];
+        let mut indices = Vec::with_capacity(self.grid.len());
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = y * self.width + x;
+                let Color(r, g, b) = self.grid[i];
+                let wanted = (
+                    (r as f64 + error[i].0).clamp(0.0, 255.0),
+                    (g as f64 + error[i].1).clamp(0.0, 255.0),
+                    (b as f64 + error[i].2).clamp(0.0, 255.0),
+                );
+
+                let index = quant.index_of(&[wanted.0 as u8, wanted.1 as u8, wanted.2 as u8, 255]);
+                indices.push(index as u8);
+
+                let diff = (
+                    wanted.0 - palette[3 * index] as f64,
+                    wanted.1 - palette[3 * index + 1] as f64,
+                    wanted.2 - palette[3 * index + 2] as f64,
+                );
+
+                let mut spread = |dx: isize, dy: usize, weight: f64| {
+                    let nx = x as isize + dx;
+                    if nx >= 0 && (nx as usize) < self.width && y + dy < self.height {
+                        let e = &mut error[(y + dy) * self.width + nx as usize];
+                        e.0 += diff.0 * weight;
+                        e.1 += diff.1 * weight;
+                        e.2 += diff.2 * weight;
+                    }
+                };
+                spread(1, 0, 7.0 / 16.0);
+                spread(-1, 1, 3.0 / 16.0);
+                spread(0, 1, 5.0 / 16.0);
+                spread(1, 1, 1.0 / 16.0);
+            }
+        }
+
+        gif::Frame::from_palette_pixels(
+            self.width as u16,
+            self.height as u16,
+            indices,
+            palette,
+            None,
+        )
+    }
+}
+
+/// How animations are encoded as GIF
+#[derive(Debug, Clone, Copy)]
+pub struct GifOptions {
+    pub fps: f64,
+    /// Delay between frames in hundredths of a second, overriding `fps`
+    pub delay: Option<u16>,
+    /// Diffuse the quantization error instead of banding
+    pub dither: bool,
+    /// Quantization speed, from 1 (best) to 30 (fastest)
+    pub speed: i32,
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        GifOptions {
+            fps: 10.0,
+            delay: None,
+            dither: false,
+            speed: 10,
+        }
+    }
+}
+
+impl GifOptions {
+    fn delay(&self) -> u16 {
+        self.delay
+            .unwrap_or_else(|| (100.0 / self.fps).round().clamp(1.0, u16::MAX as f64) as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        gif::Frame::from_rgb(self.width as u16, self.height as u16, &buf[..])
+    #[test]
+    fn tile() {
+        let images: Vec<Image> = (0..3)
+            .map(|i| {
+                let mut image = Image::new(2, 1);
+                image.set(1, 0, Color(i, 0, 0));
+                image
+            })
+            .collect();
+
+        let sheet = Image::tile(&images, 2);
+        assert_eq!((sheet.width, sheet.height), (4, 2));
+        assert_eq!(sheet[0][3], Color(1, 0, 0));
+        assert_eq!(sheet[1][1], Color(2, 0, 0));
+        assert_eq!(sheet[1][3], Color(0, 0, 0));
     }
 
-    pub fn save_as_gif(
-        frames: &Vec<Image>,
-        file: &mut File,
-        speed: usize,
-    ) -> Result<(), gif::EncodingError> {
-        assert!(!frames.is_empty());
+    #[test]
+    fn region() {
+        assert_eq!(Region::new(3, 1, 4, 4).clip(5, 3), Region::new(3, 1, 2, 2));
+        assert_eq!(Region::new(6, 0, 4, 4).clip(5, 3).width, 0);
+        assert_eq!(Region::new(1, 3, 2, 2).grow(2), Region::new(0, 1, 5, 6));
 
-        let width = frames[0].width;
-        let height = frames[0].height;
+        let tiles = Region::tiles(5, 3, 2);
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles[5], Region::new(4, 2, 1, 1));
+        assert_eq!(tiles.iter().map(|t| t.width * t.height).sum::<usize>(), 15);
+    }
 
-        let mut encoder = gif::Encoder::new(file, width as u16, height as u16, &[])?;
-        encoder.set_repeat(gif::Repeat::Infinite).unwrap();
+    #[test]
+    fn denoise_keeps_edges() {
+        let (width, height) = (16, 8);
+        let mut color = FloatImage::new(width, height);
+        let mut normal = FloatImage::new(width, height);
+        let albedo = FloatImage::new(width, height);
 
-        for frame in frames.iter() {
-            assert!(frame.height == height, frame.width == width);
-            (0..speed).for_each(|_| encoder.write_frame(&frame.to_gif_frame()).unwrap());
+        // Two surfaces side by side with a noisy gray
+        for y in 0..height {
+            for x in 0..width {
+                let noise = if (x + y) % 2 == 0 { 0.1 } else { -0.1 };
+                let (v, n) = if x < width / 2 {
+                    (0.2, (1.0, 0.0, 0.0))
+                } else {
+                    (0.8, (0.0, 1.0, 0.0))
+                };
+                color.set(x, y, (v + noise, v + noise, v + noise));
+                normal.set(x, y, n);
+            }
         }
 
-        Ok(())
+        let res = denoise(&color, &normal, &albedo, &DenoiseOptions::default());
+        for y in 0..height {
+            assert!((res.get(3, y).0 - 0.2).abs() < 0.05);
+            assert!((res.get(12, y).0 - 0.8).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn gif_delay() {
+        let options = GifOptions {
+            fps: 25.0,
+            ..Default::default()
+        };
+        assert_eq!(options.delay(), 4);
+        assert_eq!(
+            GifOptions {
+                delay: Some(7),
+                ..options
+            }
+            .delay(),
+            7
+        );
     }
 }
@@ -1,7 +1,10 @@
 use crate::common::*;
 use std::{collections::HashMap, fs::File, io::BufRead};
 
-use rand::seq::SliceRandom;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+/// Step length given to the turtle for the plants of a scene, before scaling
+pub const PLANT_LENGTH: f64 = 0.5;
 
 type LSConstant = char;
 
@@ -15,15 +18,47 @@ pub enum LSMaterial {
 }
 type LSColorTable = Vec<LSMaterial>;
 
+fn invalid(number: usize, what: &str, line: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("line {}: invalid {}: {}", number, what, line),
+    )
+}
+
+/// Whether every `[` is closed by a `]` and every `{` by a `}`, leaves not
+/// being nested
+fn balanced(values: &[char]) -> bool {
+    let (mut depth, mut in_leaf) = (0, false);
+    for &c in values.iter() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth == 0 => return false,
+            ']' => depth -= 1,
+            '{' if in_leaf => return false,
+            '}' if !in_leaf => return false,
+            '{' | '}' => in_leaf = !in_leaf,
+            _ => (),
+        }
+    }
+    depth == 0 && !in_leaf
+}
+
 #[derive(Debug, Clone)]
 pub struct LSystem {
     value: LSValues,
+    /// Expansion iteration where each symbol of the value appeared
+    ages: Vec<u64>,
+    iteration: u64,
+    /// Value and ages before each expansion, when recorded
+    history: Option<Vec<(LSValues, Vec<u64>)>>,
     rules: LSRules,
     age: u64,
     delta: f64,
     trunk: u64,
     radius: f64,
     radius_decrease: f64,
+    taper: f64,
+    joints: bool,
     color_table: LSColorTable,
 }
 
@@ -37,12 +72,17 @@ impl LSystem {
         radius_decrease: f64,
     ) -> LSystem {
         LSystem {
+            ages: vec![0; axioms.len()],
+            iteration: 0,
+            history: None,
             value: axioms,
             age,
             delta,
             trunk,
             radius,
             radius_decrease,
+            taper: 1.0,
+            joints: false,
             rules: LSRules::new(),
             color_table: LSColorTable::new(),
         }
@@ -52,6 +92,40 @@ impl LSystem {
         &self.value
     }
 
+    /// Number of expansions done
+    pub fn iteration(&self) -> u64 {
+        self.iteration
+    }
+
+    /// Keep every intermediate derivation, see `at_iteration`
+    pub fn with_history(self) -> LSystem {
+        LSystem {
+            history: Some(Vec::new()),
+            ..self
+        }
+    }
+
+    /// The L-System as it was after `n` expansions, `None` if the history was
+    /// not recorded or `n` is past the current iteration
+    pub fn at_iteration(&self, n: u64) -> Option<LSystem> {
+        let (value, ages) = if n == self.iteration {
+            (&self.value, &self.ages)
+        } else {
+            let (value, ages) = self.history.as_ref()?.get(n as usize)?;
+            (value, ages)
+        };
+
+        Some(LSystem {
+            value: value.clone(),
+            ages: ages.clone(),
+            iteration: n,
+            history: None,
+            rules: self.rules.clone(),
+            color_table: self.color_table.clone(),
+            ..*self
+        })
+    }
+
     /// Return true if key has been modified
     pub fn add_rule(&mut self, symbol: LSConstant, rule: LSValues) {
         if !self.rules.contains_key(&symbol) {
@@ -72,21 +146,69 @@ impl LSystem {
         }
     }
 
+    /// Radius factor applied at each step of a `F` run
+    pub fn with_taper(self, taper: f64) -> LSystem {
+        LSystem { taper, ..self }
+    }
+
+    /// Place spheres at branching points and heading changes to hide the gaps
+    /// between segments
+    pub fn with_joints(self, joints: bool) -> LSystem {
+        LSystem { joints, ..self }
+    }
+
+    fn parse_option(self, option: &str) -> Option<LSystem> {
+        let mut words = option[1..].split_whitespace();
+
+        match words.next() {
+            Some("taper") => {
+                let taper = words.next()?.parse::<f64>().ok()?;
+                Some(self.with_taper(taper))
+            }
+            Some("joints") => Some(self.with_joints(true)),
+            _ => None,
+        }
+    }
+
     pub fn expand(&mut self) {
-        let mut rng = rand::thread_rng();
-        self.value = self
-            .value
-            .iter()
-            .map(|&v| {
-                self.rules
-                    .get(&v)
-                    .map(|vec| vec.choose(&mut rng).unwrap().clone().to_owned())
-                    .or(Some(vec![v]))
-                    .unwrap()
-            })
-            .flatten()
-            .collect::<Vec<char>>()
-            .to_owned();
+        self.expand_with(&mut rand::thread_rng())
+    }
+
+    /// A symbol rewritten by a rule keeps its age in the first copy of itself
+    /// found in its successor, every other produced symbol is new
+    fn expand_with<R: Rng>(&mut self, rng: &mut R) {
+        if let Some(history) = &mut self.history {
+            history.push((self.value.clone(), self.ages.clone()));
+        }
+
+        let mut value = LSValues::new();
+        let mut ages = Vec::new();
+
+        for (&v, &age) in self.value.iter().zip(self.ages.iter()) {
+            match self.rules.get(&v) {
+                Some(rules) => {
+                    let successor = rules.choose(rng).unwrap();
+                    let kept = successor.iter().position(|&s| s == v);
+
+                    for (i, &s) in successor.iter().enumerate() {
+                        value.push(s);
+                        ages.push(if kept == Some(i) {
+                            age
+                        } else {
+                            self.iteration + 1
+                        });
+                    }
+                }
+                None => {
+                    value.push(v);
+                    ages.push(age);
+                }
+            }
+        }
+
+        self.value = value;
+        self.ages = ages;
+        self.iteration += 1;
     }
 
     pub fn generate(mut self) -> LSystem {
@@ -97,36 +219,77 @@ impl LSystem {
         self
     }
 
+    /// Generate with a seeded random generator, so that stochastic rules give
+    /// the same plant for the same seed
+    pub fn generate_seeded(mut self, seed: u64) -> LSystem {
+        let mut rng = StdRng::seed_from_u64(seed);
+        for _ in 1..=self.age {
+            self.expand_with(&mut rng)
+        }
+        self
+    }
+
+    /// The prelude may be followed by option lines starting with `%`, before
+    /// the axiom:
+    /// - `%taper <factor>`: radius factor applied at each step of a `F` run
+    /// - `%joints`: place spheres at the joints between segments
     pub fn from_file(path: &str) -> std::io::Result<LSystem> {
         println!("Generating L-System from {}", path);
 
-        let file = File::open(path)?;
-        let mut lines = std::io::BufReader::new(file).lines();
+        LSystem::parse(std::io::BufReader::new(File::open(path)?))
+    }
+
+    /// Parse an L-system in the format of `from_file`
+    pub fn parse<R: BufRead>(reader: R) -> std::io::Result<LSystem> {
+        let mut lines = reader.lines().enumerate();
+        let mut next_line = || match lines.next() {
+            Some((i, line)) => Ok((i + 1, line?)),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "missing prelude or axiom",
+            )),
+        };
+        fn parse<T: std::str::FromStr>(
+            (number, line): (usize, String),
+            what: &str,
+        ) -> std::io::Result<T> {
+            line.trim()
+                .parse::<T>()
+                .map_err(|_| invalid(number, what, &line))
+        }
 
         // Prelude
-        let age = lines.next().unwrap()?.parse::<u64>().unwrap();
-        let delta = lines.next().unwrap()?.parse::<f64>().unwrap().to_radians();
-        let trunk = lines.next().unwrap()?.parse::<u64>().unwrap();
-        let radius = lines.next().unwrap()?.parse::<f64>().unwrap();
-        let radius_decrease = lines.next().unwrap()?.parse::<f64>().unwrap();
+        let age = parse::<u64>(next_line()?, "age")?;
+        let delta = parse::<f64>(next_line()?, "angle")?.to_radians();
+        let trunk = parse::<u64>(next_line()?, "trunk")?;
+        let radius = parse::<f64>(next_line()?, "radius")?;
+        let radius_decrease = parse::<f64>(next_line()?, "radius decrease")?;
 
         let mut colors = LSColorTable::new();
-
-        for val in lines.next().unwrap()?.split_whitespace() {
-            colors.push(match u32::from_str_radix(&val[0..6], 16) {
-                Ok(_) => {
-                    let r = u8::from_str_radix(&val[0..2], 16).unwrap();
-                    let g = u8::from_str_radix(&val[2..4], 16).unwrap();
-                    let b = u8::from_str_radix(&val[4..6], 16).unwrap();
-
-                    LSMaterial::Uniform(Color(r, g, b))
+        let (color_number, color_line) = next_line()?;
+        for val in color_line.split_whitespace() {
+            colors.push(match val.get(0..6).map(|v| u32::from_str_radix(v, 16)) {
+                Some(Ok(rgb)) => {
+                    LSMaterial::Uniform(Color((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
                 }
-                Err(_) => LSMaterial::Texture(val.to_string()),
+                _ => LSMaterial::Texture(val.to_string()),
             })
         }
+        if colors.is_empty() {
+            return Err(invalid(color_number, "colors", &color_line));
+        }
 
         // End of prelude
-        let axioms = lines.next().unwrap()?;
+        let (mut number, mut line) = next_line()?;
+        let mut options = Vec::new();
+        while line.starts_with('%') {
+            options.push((number, line));
+            (number, line) = next_line()?;
+        }
+        let axioms = line;
+        if !balanced(&axioms.chars().collect::<LSValues>()) {
+            return Err(invalid(number, "brackets", &axioms));
+        }
 
         let mut res = LSystem::new(
             axioms.chars().collect(),
@@ -138,8 +301,27 @@ impl LSystem {
         )
         .with_colors(colors);
 
-        for rule in lines {
-            let mut chars: LSValues = rule?.chars().collect();
+        for (number, option) in options.iter() {
+            res = res
+                .parse_option(option)
+                .ok_or_else(|| invalid(*number, "option", option))?;
+        }
+
+        for (i, rule) in lines {
+            let rule = rule?;
+            if rule.trim().is_empty() {
+                continue;
+            }
+            // Rules are written as the symbol, a separator and the successor
+            let mut chars: LSValues = rule.chars().collect();
+            // Brackets are never rewritten so that balanced successors keep
+            // the whole word balanced
+            if chars.len() < 2 || "[]{}".contains(chars[0]) {
+                return Err(invalid(i + 1, "rule", &rule));
+            }
+            if !balanced(&chars[2..]) {
+                return Err(invalid(i + 1, "brackets", &rule));
+            }
 
             let token = chars[0];
 
@@ -159,18 +341,68 @@ impl ToString for LSystem {
     }
 }
 
-use crate::scene::ObjectContainer;
+use crate::scene::texture::TextureCache;
+use crate::scene::{ObjectContainer, ObjectType};
+use imagelib::RgbImage;
+use std::rc::Rc;
+
+/// Geometric primitive drawn by the turtle
+#[derive(Debug, Clone)]
+pub enum LSPrimitive {
+    /// Branch segment, going from radius `ra` at `a` to `rb` at `b`
+    Edge {
+        a: Point,
+        b: Point,
+        ra: f64,
+        rb: f64,
+    },
+    /// Sphere hiding the gap between two segments
+    Joint {
+        center: Point,
+        radius: f64,
+    },
+    Fruit {
+        center: Point,
+        radius: f64,
+    },
+    /// Planar polygon
+    Leaf {
+        points: Vec<Point>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct LSShape {
+    pub primitive: LSPrimitive,
+    /// Index in the color table
+    pub material: usize,
+}
 
-type LSTResult = Vec<ObjectContainer>;
+/// Result of the interpretation of an L-System, independent of the renderer
+#[derive(Debug, Clone)]
+pub struct LSGeometry {
+    pub shapes: Vec<LSShape>,
+    pub materials: Vec<LSMaterial>,
+}
 
 impl LSystem {
-    pub fn translate(
-        self,
+    /// Time at which every symbol is fully grown
+    ///
+    /// Symbols appearing at the n-th expansion grow between times n and n + 1.
+    pub fn growth_duration(&self) -> f64 {
+        (self.iteration + 1) as f64
+    }
+
+    /// Interpret the value at `time`, segments, leaves and fruits being scaled
+    /// by how much they grew since they appeared
+    pub fn shapes_at(
+        &self,
+        time: f64,
         pos: Point,
         direction: NormalVector,
         right: NormalVector,
         length: f64,
-    ) -> LSTResult {
+    ) -> LSGeometry {
         let state = LSTState {
             pos,
             direction,
@@ -178,15 +410,121 @@ impl LSystem {
             color: 0,
             radius: self.radius,
             obj_index: 0,
+            heading: None,
+            end_radius: 0.0,
+            joint: false,
         };
         LSTranslator::new(
             self.delta,
             self.trunk,
             length,
             self.radius_decrease,
-            self.color_table,
+            self.taper,
+            self.joints,
+            self.color_table.clone(),
         )
-        .run(state, &self.value)
+        .run(state, &self.value, &self.ages, time)
+    }
+
+    pub fn shapes(
+        self,
+        pos: Point,
+        direction: NormalVector,
+        right: NormalVector,
+        length: f64,
+    ) -> LSGeometry {
+        self.shapes_at(f64::INFINITY, pos, direction, right, length)
+    }
+
+    pub fn translate(
+        self,
+        pos: Point,
+        direction: NormalVector,
+        right: NormalVector,
+        length: f64,
+    ) -> ObjectContainer {
+        self.shapes(pos, direction, right, length).to_objects()
+    }
+}
+
+impl LSGeometry {
+    fn make_objects(&self, shape: &LSShape, images: &[Option<Rc<RgbImage>>]) -> ObjectContainer {
+        use crate::scene::texture::UVMapTexture;
+        use crate::scene::texture::UniformTexture;
+        use crate::scene::{Cone, Cylinder, Sphere, Triangle};
+
+        let material = &self.materials[shape.material];
+        let image = &images[shape.material];
+
+        // Build the object with the right texture type for the material
+        macro_rules! textured {
+            ($diff:expr, $spec:expr, |$texture:ident| $obj:expr) => {
+                match material {
+                    LSMaterial::Uniform(c) => {
+                        let $texture = UniformTexture::new(*c, $diff, $spec);
+                        Box::new($obj) as ObjectType
+                    }
+                    LSMaterial::Texture(_) => {
+                        // Every texture material has its image loaded
+                        let image = image.clone().unwrap();
+                        let $texture = UVMapTexture::from_image(image, $diff, $spec);
+                        Box::new($obj) as ObjectType
+                    }
+                }
+            };
+        }
+
+        match &shape.primitive {
+            LSPrimitive::Edge { a, b, ra, rb } if ra == rb => {
+                vec![textured!(1.0, 0.3, |t| Cylinder::new(*a, *b, *ra, t))]
+            }
+            LSPrimitive::Edge { a, b, ra, rb } => {
+                vec![textured!(1.0, 0.3, |t| Cone::new(*a, *b, *ra, *rb, t))]
+            }
+            LSPrimitive::Joint { center, radius } => {
+                vec![textured!(1.0, 0.3, |t| Sphere::new(*center, *radius, t))]
+            }
+            LSPrimitive::Fruit { center, radius } => {
+                vec![textured!(1.0, 0.7, |t| Sphere::new(*center, *radius, t))]
+            }
+            LSPrimitive::Leaf { points } => {
+                let mut leaf = points.clone();
+                let mut res = ObjectContainer::new();
+
+                let v0 = leaf.pop().unwrap();
+                let mut prev = leaf.pop().unwrap();
+
+                while let Some(next) = leaf.pop() {
+                    res.push(textured!(1.0, 0.4, |t| Triangle::new((v0, prev, next), t)));
+                    prev = next;
+                }
+                res
+            }
+        }
+    }
+
+    /// Build the objects to render
+    pub fn to_objects(&self) -> ObjectContainer {
+        self.to_objects_with(&mut TextureCache::default())
+            .unwrap_or_else(|e| panic!("Could not load texture {}", e))
+    }
+
+    /// Build the objects to render, taking the texture images from `textures`
+    pub fn to_objects_with(&self, textures: &mut TextureCache) -> std::io::Result<ObjectContainer> {
+        let images = self
+            .materials
+            .iter()
+            .map(|material| match material {
+                LSMaterial::Uniform(_) => Ok(None),
+                LSMaterial::Texture(path) => textures.load(path).map(Some),
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(self
+            .shapes
+            .iter()
+            .flat_map(|shape| self.make_objects(shape, &images))
+            .collect())
     }
 }
 
@@ -198,6 +536,12 @@ struct LSTState {
     color: usize,
     radius: f64,
     obj_index: usize,
+    // Direction of the segment ending at pos, if any
+    heading: Option<NormalVector>,
+    // Radius of the segment ending at pos, which the next ones start with
+    end_radius: f64,
+    // Whether a joint has already been placed at pos
+    joint: bool,
 }
 
 impl LSTState {
@@ -239,9 +583,11 @@ struct LSTranslator {
     trunk: u64,
     length: f64,
     radius_decrease: f64,
+    taper: f64,
+    joints: bool,
     saved_states: LSTStack,
     color_table: LSColorTable,
-    res: LSTResult,
+    shapes: Vec<LSShape>,
 }
 
 impl LSTranslator {
@@ -250,6 +596,8 @@ impl LSTranslator {
         trunk: u64,
         length: f64,
         radius_decrease: f64,
+        taper: f64,
+        joints: bool,
         color_table: LSColorTable,
     ) -> LSTranslator {
         LSTranslator {
@@ -257,131 +605,95 @@ impl LSTranslator {
             trunk,
             length,
             radius_decrease,
+            taper,
+            joints,
             saved_states: LSTStack::new(),
             color_table,
-            res: LSTResult::new(),
+            shapes: Vec::new(),
         }
     }
 
-    fn get_material(&self, state: &LSTState) -> LSMaterial {
-        self.color_table[state.color].clone()
+    fn push(&mut self, state: &LSTState, primitive: LSPrimitive) {
+        self.shapes.push(LSShape {
+            primitive,
+            material: state.color,
+        })
     }
 
-    fn add_fruit(&mut self, state: &LSTState) {
-        use crate::scene::texture::UVMapTexture;
-        use crate::scene::texture::UniformTexture;
-        use crate::scene::Sphere;
-
+    fn add_fruit(&mut self, state: &LSTState, growth: f64) {
         const FRUIT_RADIUS: f64 = 2.5;
 
-        for i in state.obj_index..self.res.len() {
-            match self.get_material(&state) {
-                LSMaterial::Uniform(c) => {
-                    let sphere = Sphere::new(
-                        state.pos,
-                        self.length / FRUIT_RADIUS,
-                        UniformTexture::new(c, 1.0, 0.7),
-                    );
-                    self.res[i].push(Box::new(sphere));
-                }
-                LSMaterial::Texture(t) => {
-                    let sphere = Sphere::new(
-                        state.pos,
-                        self.length / FRUIT_RADIUS,
-                        UVMapTexture::new(t, 1.0, 0.7),
-                    );
-                    self.res[i].push(Box::new(sphere));
-                }
-            }
+        if growth <= 0.0 {
+            return;
         }
+
+        self.push(
+            state,
+            LSPrimitive::Fruit {
+                center: state.pos,
+                radius: growth * self.length / FRUIT_RADIUS,
+            },
+        )
     }
 
-    fn add_edge(&mut self, state: &LSTState, dst: Point) {
-        use crate::scene::texture::UVMapTexture;
-        use crate::scene::texture::UniformTexture;
-        use crate::scene::Cylinder;
-
-        for i in state.obj_index..self.res.len() {
-            match self.get_material(&state) {
-                LSMaterial::Uniform(c) => {
-                    let cylinder = Cylinder::new(
-                        state.pos,
-                        dst,
-                        state.radius,
-                        UniformTexture::new(c, 1.0, 0.3),
-                    );
-                    self.res[i].push(Box::new(cylinder));
-                }
-                LSMaterial::Texture(t) => {
-                    let cylinder =
-                        Cylinder::new(state.pos, dst, state.radius, UVMapTexture::new(t, 1.0, 0.3));
-                    self.res[i].push(Box::new(cylinder));
-                }
-            };
+    fn add_joint(&mut self, state: &mut LSTState) {
+        if !self.joints || state.joint || state.heading.is_none() {
+            return;
         }
+        state.joint = true;
+
+        self.push(
+            state,
+            LSPrimitive::Joint {
+                center: state.pos,
+                radius: state.end_radius,
+            },
+        )
     }
 
-    fn generate_leaf(&mut self, state: &LSTState, leaf: &mut LSTLeave) {
-        assert!(leaf.len() >= 3);
-
-        use crate::scene::texture::UVMapTexture;
-        use crate::scene::texture::UniformTexture;
-        use crate::scene::Triangle;
-
-        let v0 = leaf.pop().unwrap();
-        let mut prev = leaf.pop().unwrap();
+    fn add_edge(&mut self, state: &LSTState, dst: Point, start_radius: f64, end_radius: f64) {
+        self.push(
+            state,
+            LSPrimitive::Edge {
+                a: state.pos,
+                b: dst,
+                ra: start_radius,
+                rb: end_radius,
+            },
+        )
+    }
 
-        while !leaf.is_empty() {
-            let next = leaf.pop().unwrap();
-            for i in state.obj_index..self.res.len() {
-                match self.get_material(&state) {
-                    LSMaterial::Uniform(c) => {
-                        let triangle =
-                            Triangle::new((v0, prev, next), UniformTexture::new(c, 1.0, 0.4));
-                        self.res[i].push(Box::new(triangle));
-                    }
-                    LSMaterial::Texture(t) => {
-                        let triangle =
-                            Triangle::new((v0, prev, next), UVMapTexture::new(t, 1.0, 0.4));
-                        self.res[i].push(Box::new(triangle));
-                    }
-                };
-            }
-            prev = next;
+    fn generate_leaf(&mut self, state: &LSTState, leaf: &mut LSTLeave) {
+        // Fewer points do not make a polygon
+        if leaf.len() < 3 {
+            return;
         }
-    }
 
-    fn compute_dst(&self, state: &LSTState) -> Point {
-        let dst =
-            (Vector::from(ORIGIN, state.pos) + self.length * state.direction.vector()).to_point();
-        dst
+        let points = std::mem::take(leaf);
+        self.push(state, LSPrimitive::Leaf { points })
     }
 
-    fn compute_res_size(&mut self, values: &LSValues) {
-        let mut size = 0;
-        self.res.push(Vec::new());
-
-        for val in values {
-            match val {
-                '[' => {
-                    size += 1;
-                    if size == self.res.len() {
-                        self.res.push(Vec::new());
-                        println!("LSystem steps increased to {}", size);
-                    }
-                }
-                ']' => size -= 1,
-                _ => (),
-            }
-        }
+    fn compute_dst(&self, state: &LSTState, growth: f64) -> Point {
+        let dst = (Vector::from(ORIGIN, state.pos)
+            + growth * self.length * state.direction.vector())
+        .to_point();
+        dst
     }
 
-    fn run(mut self, initial_state: LSTState, values: &LSValues) -> LSTResult {
+    /// Interpret `values` at `time`, `ages` giving when each symbol appeared
+    fn run(
+        mut self,
+        initial_state: LSTState,
+        values: &LSValues,
+        ages: &[u64],
+        time: f64,
+    ) -> LSGeometry {
         let mut state = initial_state;
         let mut leaf = LSTLeave::new();
+        let mut leaf_growth: f64 = 0.0;
         let mut in_leaf = false;
 
-        self.compute_res_size(values);
+        let growth = |i: usize| (time - ages[i] as f64).clamp(0.0, 1.0);
 
         let mut i = 0;
         let len = values.len();
@@ -389,17 +701,47 @@ impl LSTranslator {
             let val = values[i];
             match val {
                 'f' | 'F' => {
+                    if !in_leaf {
+                        if let Some(heading) = state.heading {
+                            if NormalVector::dot_product(&heading, &state.direction) < 0.9999 {
+                                self.add_joint(&mut state);
+                            }
+                        }
+                    }
+
                     let start_state = state;
-                    let mut dst = self.compute_dst(&state);
+                    let start_growth = growth(i);
+                    let mut dst = self.compute_dst(&state, growth(i));
                     state.pos = dst;
-                    while i + 1 < len && (values[i + 1] == 'f' || values[i + 1] == 'F') {
-                        dst = self.compute_dst(&state);
-                        state.pos = dst;
+                    state.radius *= self.taper;
+                    // Segments growing differently are drawn separately
+                    while i + 1 < len
+                        && (values[i + 1] == 'f' || values[i + 1] == 'F')
+                        && growth(i + 1) == start_growth
+                    {
                         i += 1;
+                        dst = self.compute_dst(&state, growth(i));
+                        state.pos = dst;
+                        state.radius *= self.taper;
                     }
                     if !in_leaf {
-                        self.add_edge(&start_state, dst)
+                        // Segments that did not start growing are not drawn
+                        if start_growth > 0.0 {
+                            // Segments go on from the end of the one they
+                            // continue, narrowing to their own radius
+                            let start_radius = match start_state.heading {
+                                Some(_) => start_state.end_radius,
+                                None => start_state.radius * start_growth,
+                            };
+                            let end_radius = state.radius * start_growth;
+                            self.add_edge(&start_state, dst, start_radius, end_radius);
+                            state.heading = Some(state.direction);
+                            state.end_radius = end_radius;
+                            state.joint = false;
+                        }
                     } else {
+                        state.heading = None;
+                        leaf_growth = leaf_growth.max(growth(i));
                         if state.obj_index >= self.trunk as usize {
                             leaf.push(dst)
                         }
@@ -407,7 +749,7 @@ impl LSTranslator {
                 }
                 '@' => {
                     if state.obj_index >= self.trunk as usize {
-                        self.add_fruit(&state);
+                        self.add_fruit(&state, growth(i));
                     }
                 }
                 '!' => state.radius *= self.radius_decrease,
@@ -420,19 +762,30 @@ impl LSTranslator {
                 '/' => state.rotate_roll(-self.delta),
                 '|' => state.rotate_turn(180f64.to_radians()),
                 '[' => {
+                    if !in_leaf {
+                        self.add_joint(&mut state);
+                    }
                     self.saved_states.push(state.clone());
                     state.obj_index += 1;
                 }
-                ']' => state = self.saved_states.pop().unwrap(),
+                ']' => {
+                    if let Some(saved) = self.saved_states.pop() {
+                        state = saved;
+                    }
+                }
                 '{' => {
-                    assert!(leaf.is_empty());
+                    // A leaf opened inside another one starts over
+                    leaf.clear();
+                    leaf_growth = 0.0;
                     //self.saved_states.push(state.clone());
                     in_leaf = true;
                 }
                 '}' => {
-                    if state.obj_index >= self.trunk as usize {
+                    if state.obj_index >= self.trunk as usize && leaf_growth > 0.0 {
                         self.generate_leaf(&state, &mut leaf);
                     }
+                    leaf.clear();
+                    leaf_growth = 0.0;
                     //state = self.saved_states.pop().unwrap();
                     in_leaf = false;
                 }
@@ -442,7 +795,10 @@ impl LSTranslator {
             i += 1;
         }
 
-        self.res
+        LSGeometry {
+            shapes: self.shapes,
+            materials: self.color_table,
+        }
     }
 }
 
@@ -470,4 +826,152 @@ mod tests {
         lsystem.expand();
         assert_eq!(lsystem.to_string(), "abaababa");
     }
+
+    #[test]
+    fn ages() {
+        let mut lsystem = LSystem::new(vec!['F', 'A'], 0, 0.0, 0, 0.0, 0.0);
+        lsystem.add_rule('F', vec!['F', 'F']);
+        lsystem.add_rule('A', vec!['[', 'F', ']', 'A']);
+
+        lsystem.expand();
+        lsystem.expand();
+        assert_eq!(lsystem.to_string(), "FFFF[FF][F]A");
+        assert_eq!(lsystem.ages, vec![0, 2, 1, 2, 1, 1, 2, 1, 2, 2, 2, 0]);
+    }
+
+    #[test]
+    fn continuous_growth() {
+        let mut lsystem = LSystem::new(vec!['F', 'A'], 0, 0.0, 0, 0.1, 1.0);
+        lsystem.add_rule('A', vec!['F', 'A']);
+        lsystem.expand();
+
+        let up = Vector::new(1.0, 0.0, 0.0).normalize();
+        let right = Vector::new(0.0, 1.0, 0.0).normalize();
+        let edges = |time: f64| {
+            lsystem
+                .shapes_at(time, ORIGIN, up, right, 1.0)
+                .shapes
+                .iter()
+                .map(|s| match s.primitive {
+                    LSPrimitive::Edge { b, rb, .. } => (b.0, rb),
+                    _ => panic!("Unexpected primitive"),
+                })
+                .collect::<Vec<(f64, f64)>>()
+        };
+
+        assert!(edges(0.0).is_empty());
+        assert_eq!(edges(0.5), vec![(0.5, 0.05)]);
+        // The second segment starts growing at the tip of the first one
+        assert_eq!(edges(1.5), vec![(1.0, 0.1), (1.5, 0.05)]);
+        assert_eq!(edges(lsystem.growth_duration()), vec![(2.0, 0.1)]);
+    }
+
+    #[test]
+    fn taper() {
+        let lsystem =
+            LSystem::new("FF[F]!F".chars().collect(), 0, 0.0, 0, 1.0, 0.5).with_taper(0.5);
+
+        let up = Vector::new(1.0, 0.0, 0.0).normalize();
+        let right = Vector::new(0.0, 1.0, 0.0).normalize();
+        let radii = lsystem
+            .shapes(ORIGIN, up, right, 1.0)
+            .shapes
+            .iter()
+            .map(|s| match s.primitive {
+                LSPrimitive::Edge { ra, rb, .. } => (ra, rb),
+                _ => panic!("Unexpected primitive"),
+            })
+            .collect::<Vec<(f64, f64)>>();
+
+        // The run of two segments is a single cone, and both the branch and
+        // the thinner segment after it start where it ends
+        assert_eq!(radii, vec![(1.0, 0.25), (0.25, 0.125), (0.25, 0.0625)]);
+    }
+
+    #[test]
+    fn joints() {
+        let lsystem = LSystem::new(
+            "F+F[F]".chars().collect(),
+            0,
+            90.0f64.to_radians(),
+            0,
+            1.0,
+            1.0,
+        );
+
+        let up = Vector::new(1.0, 0.0, 0.0).normalize();
+        let right = Vector::new(0.0, 1.0, 0.0).normalize();
+        let joints = |lsystem: LSystem| {
+            lsystem
+                .shapes(ORIGIN, up, right, 1.0)
+                .shapes
+                .iter()
+                .filter_map(|s| match s.primitive {
+                    LSPrimitive::Joint { center, radius } => Some((center.0, radius)),
+                    _ => None,
+                })
+                .collect::<Vec<(f64, f64)>>()
+        };
+
+        assert!(joints(lsystem.clone()).is_empty());
+        // At the turn and at the branch, not along the straight branch
+        assert_eq!(
+            joints(lsystem.clone().with_joints(true)),
+            vec![(1.0, 1.0), (1.0, 1.0)]
+        );
+        // As thick as the tapered segment they end
+        assert_eq!(
+            joints(lsystem.with_joints(true).with_taper(0.5)),
+            vec![(1.0, 0.5), (1.0, 0.25)]
+        );
+    }
+
+    #[test]
+    fn history() {
+        let mut lsystem = LSystem::new(vec!['a'], 0, 0.0, 0, 0.0, 0.0).with_history();
+        lsystem.add_rule('a', vec!['a', 'b']);
+        lsystem.add_rule('b', vec!['a']);
+
+        for _ in 0..4 {
+            lsystem.expand();
+        }
+
+        assert_eq!(lsystem.at_iteration(0).unwrap().to_string(), "a");
+        assert_eq!(lsystem.at_iteration(2).unwrap().to_string(), "aba");
+        assert_eq!(lsystem.at_iteration(4).unwrap().to_string(), "abaababa");
+        assert_eq!(lsystem.at_iteration(2).unwrap().iteration(), 2);
+        assert!(lsystem.at_iteration(5).is_none());
+    }
+
+    #[test]
+    fn parse() {
+        let text = "3\n22.5\n0\n0.2\n0.7\n80604D bark.png\n%taper 0.9\nA\nA FA\n\n";
+        let lsystem = LSystem::parse(text.as_bytes()).unwrap();
+        assert_eq!(lsystem.to_string(), "A");
+        assert_eq!(lsystem.taper, 0.9);
+        assert!(matches!(
+            lsystem.color_table[..],
+            [
+                LSMaterial::Uniform(Color(0x80, 0x60, 0x4D)),
+                LSMaterial::Texture(_)
+            ]
+        ));
+
+        let err = LSystem::parse("3\n22.5\nx\n".as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "line 3: invalid trunk: x");
+        let err = LSystem::parse("3\n22.5\n0\n0.2\n0.7\n \nA\n".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "line 6: invalid colors:  ");
+        assert!(LSystem::parse("3\n22.5\n0\n".as_bytes()).is_err());
+        assert!(LSystem::parse("3\n22.5\n0\n0.2\n0.7\nfff\n%twist\nA\n".as_bytes()).is_err());
+        assert!(LSystem::parse("3\n22.5\n0\n0.2\n0.7\nfff\nA\nB\n".as_bytes()).is_err());
+
+        let err = LSystem::parse("3\n22.5\n0\n0.2\n0.7\nfff\nA]\n".as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "line 7: invalid brackets: A]");
+        for rule in ["A F[A", "A F]A[", "A {f{f.}}", "A f.}", "[ F"].iter() {
+            let text = format!("3\n22.5\n0\n0.2\n0.7\nfff\nA\n{}\n", rule);
+            assert!(LSystem::parse(text.as_bytes()).is_err(), "{}", rule);
+        }
+        assert!(LSystem::parse("3\n22.5\n0\n0.2\n0.7\nfff\n{A}\nA [f.]f.\n".as_bytes()).is_ok());
+    }
 }
@@ -1,26 +1,44 @@
 mod common;
 mod engine;
+mod export;
 mod geometry;
 mod image;
 mod lsystem;
+mod post;
 mod premade_scenes;
+#[cfg(feature = "preview")]
+mod preview;
+mod scatter;
 mod scene;
+mod scene_file;
+mod sink;
+mod watcher;
 
 use std::fs::File;
+use std::io::BufWriter;
 use std::path::Path;
+use std::time::Duration;
 
 use common::{Color, Point};
 use geometry::Vector;
 use image::Image;
+use lsystem::PLANT_LENGTH;
 
-const GIF_SPEED: usize = 2;
+const EXPORT_SIDES: usize = 8;
+const DEFAULT_FPS: f64 = 10.0;
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
 
 fn save_image(path: &str, image: &Image) {
     let path = Path::new(&path);
     let file = File::create(path);
 
     if let Ok(mut file) = file {
-        match image.to_ppm(&mut file) {
+        let res = if path.extension().is_some_and(|e| e == "png") {
+            image.to_png(BufWriter::new(file))
+        } else {
+            image.to_ppm(&mut file)
+        };
+        match res {
             Ok(_) => println!("Success!"),
             _ => println!("Could not write: {}", path.display()),
         }
@@ -29,232 +47,721 @@ fn save_image(path: &str, image: &Image) {
     }
 }
 
-fn save_gif(path: &str, image: &Vec<Image>) {
-    let path = Path::new(&path);
-    let file = File::create(path);
-
-    if let Ok(mut file) = file {
-        match Image::save_as_gif(image, &mut file, GIF_SPEED) {
-            Ok(_) => println!("Success!"),
-            _ => println!("Could not write: {}", path.display()),
-        }
+/// Where the frames of an animation go: an image sequence with `--sequence`,
+/// an encoder with `--pipe=<command>`, an APNG for `.apng` paths or a GIF
+/// otherwise. With `--resume`, frames of a sequence already on disk are not
+/// rendered again.
+///
+/// The frame rate is given by `--fps=<n>`, which GIF frame delays can override
+/// with `--delay=<hundredths of a second>`, and `--dither` diffuses the
+/// quantization error of GIF frames
+fn frame_sink(args: &[String], path: &str, nb_frames: usize) -> Box<dyn sink::FrameSink> {
+    let fps = args
+        .iter()
+        .find_map(|a| a.strip_prefix("--fps="))
+        .map_or(DEFAULT_FPS, |f| f.parse::<f64>().unwrap());
+
+    if args.iter().any(|a| a == "--sequence") {
+        Box::new(sink::Sequence::new(path).with_resume(args.iter().any(|a| a == "--resume")))
+    } else if let Some(command) = args.iter().find_map(|a| a.strip_prefix("--pipe=")) {
+        Box::new(sink::Pipe::new(command, fps))
+    } else if path.ends_with(".apng") {
+        Box::new(
+            sink::Apng::create(path, nb_frames, fps)
+                .unwrap_or_else(|e| panic!("Could not open {}: {}", path, e)),
+        )
     } else {
-        println!("Could not open: {}", path.display())
+        let options = image::GifOptions {
+            fps,
+            delay: args
+                .iter()
+                .find_map(|a| a.strip_prefix("--delay="))
+                .map(|d| d.parse::<u16>().unwrap()),
+            dither: args.iter().any(|a| a == "--dither"),
+            ..Default::default()
+        };
+        Box::new(sink::Gif::new(path, options))
     }
 }
 
-fn generate_multiple_plants(args: &Vec<String>) -> crate::scene::ObjectContainer {
-    let mut objs: scene::ObjectContainer = vec![Box::new(
-        triangle! {Point(-10.0, 2000.0, 2000.0), Point(-10.0, -2000.0, 2000.0), Point(-10.0, -2000.0, -2000.0); <uniform>(Color(124,252,0), 1.0, 1.0)},
-    )];
+fn report(path: &str, res: std::io::Result<()>) {
+    match res {
+        Ok(_) => println!("Success!"),
+        Err(e) => println!("Could not write {}: {}", path, e),
+    }
+}
 
-    objs.append({
-        let lsystem = lsystem::LSystem::from_file(&args[2]).unwrap().generate();
+/// Images and props read by `build_engine`, kept to be read again only when
+/// their files change
+#[derive(Default)]
+struct Assets {
+    textures: scene::texture::TextureCache,
+    props: scene::obj::PropCache,
+}
 
-        &mut lsystem
-            .translate(
-                Point(-10.0, 0.0, 20.0),
-                //Point(-10.0, 10.0, 30.0),
-                Vector::new(1.0, 0.0, 0.0).normalize(),
-                Vector::new(0.0, -1.0, 0.0).normalize(),
-                //Vector::new(1.0, -1.0, -1.0).normalize(),
-                0.5,
-            )
-            .last_mut()
-            .unwrap()
-    });
+/// Files given on the command line that the engine is built from, besides the
+/// L-system and scene file: the OBJ props and the environment map
+fn input_files(args: &[String]) -> Vec<&str> {
+    let props = args
+        .iter()
+        .filter_map(|a| a.strip_prefix("--obj="))
+        .map(|prop| prop.split(':').next().unwrap());
+    let background = args
+        .iter()
+        .find_map(|a| a.strip_prefix("--background="))
+        .filter(|value| background_color(value).is_none());
+
+    props.chain(background).collect()
+}
 
-    objs.append({
-        let lsystem = lsystem::LSystem::from_file(&args[2]).unwrap().generate();
+/// Background given by name or as an `rrggbb` color, rather than an image
+fn background_color(value: &str) -> Option<scene::Background> {
+    let up = Vector::new(1.0, 0.0, 0.0).normalize();
+
+    match value {
+        "sky" => Some(scene::Background::Sky {
+            up,
+            sun: Vector::new(1.0, -0.6, -0.3).normalize(),
+        }),
+        "gradient" => Some(scene::Background::Gradient {
+            up,
+            top: Color(135, 206, 235),
+            bottom: Color(255, 255, 255),
+        }),
+        hex if hex.len() == 6 && u32::from_str_radix(hex, 16).is_ok() => {
+            let r = u8::from_str_radix(&hex[0..2], 16).unwrap();
+            let g = u8::from_str_radix(&hex[2..4], 16).unwrap();
+            let b = u8::from_str_radix(&hex[4..6], 16).unwrap();
+            Some(scene::Background::Solid(Color(r, g, b)))
+        }
+        _ => None,
+    }
+}
 
-        &mut lsystem
-            .translate(
-                Point(-10.0, 4.0, 25.0),
-                //Point(-10.0, 10.0, 30.0),
-                Vector::new(1.0, 0.0, 0.0).normalize(),
-                Vector::new(0.0, -1.0, 0.0).normalize(),
-                //Vector::new(1.0, -1.0, -1.0).normalize(),
-                0.4,
-            )
-            .last_mut()
-            .unwrap()
-    });
+fn parse_background(
+    args: &[String],
+    textures: &mut scene::texture::TextureCache,
+) -> scene::Background {
+    let value = args
+        .iter()
+        .find_map(|a| a.strip_prefix("--background="))
+        .unwrap_or("000000");
+
+    background_color(value).unwrap_or_else(|| match textures.load(value) {
+        Ok(image) => scene::Background::from_image(
+            image,
+            Vector::new(1.0, 0.0, 0.0).normalize(),
+            Vector::new(0.0, 0.0, 1.0).normalize(),
+        ),
+        Err(e) => {
+            println!("Could not load background {}", e);
+            scene::Background::Solid(Color(0, 0, 0))
+        }
+    })
+}
 
-    objs.append({
-        let lsystem = lsystem::LSystem::from_file(&args[2]).unwrap().generate();
+/// Follow the camera track over `nb_frames` frames, or orbit around the center
+/// of view without one
+fn camera_motion(
+    track: Option<&scene::CameraTrack>,
+    nb_frames: usize,
+) -> impl FnMut(&mut scene::Camera) + '_ {
+    let mut frame = 0;
+
+    move |c| match track {
+        Some(track) => {
+            frame += 1;
+            let (start, end) = track.range();
+            let t = frame as f64 / (nb_frames.max(2) - 1) as f64;
+            c.look(&track.sample(start + (end - start) * t))
+        }
+        None => c.rotate_around_center_of_view(10.0f64.to_radians()),
+    }
+}
 
-        &mut lsystem
-            .translate(
-                Point(-10.0, -8.0, 24.0),
-                //Point(-10.0, 10.0, 30.0),
-                Vector::new(1.0, 0.0, 0.0).normalize(),
-                Vector::new(0.0, -1.0, 0.0).normalize(),
-                //Vector::new(1.0, -1.0, -1.0).normalize(),
-                0.3,
-            )
-            .last_mut()
-            .unwrap()
-    });
+/// Projection is given as `ortho:<width>`, `fisheye[:<fov>]` or `equirect`
+fn parse_projection(cam: scene::Camera, value: &str) -> scene::Camera {
+    let mut split = value.splitn(2, ':');
+    let name = split.next().unwrap();
+    let param = split.next().map(|v| v.parse::<f64>().unwrap());
+
+    match name {
+        "ortho" => {
+            let width = param.unwrap_or(20.0);
+            let height = width * cam.height as f64 / cam.width as f64;
+            cam.with_projection(scene::Orthographic::new(width, height))
+        }
+        "fisheye" => {
+            let aspect_ratio = cam.width as f64 / cam.height as f64;
+            cam.with_projection(scene::Fisheye::new(param.unwrap_or(180.0), aspect_ratio))
+        }
+        "equirect" => cam.with_projection(scene::Equirectangular),
+        _ => panic!("Unknown projection: {}", value),
+    }
+}
 
-    objs.append({
-        let lsystem = lsystem::LSystem::from_file(&args[2]).unwrap().generate();
+/// Distribution is given as `--scatter=poisson:<min distance>` or `--scatter=grid:<cell>`
+fn parse_distribution(args: &[String]) -> scatter::Distribution {
+    let value = args
+        .iter()
+        .find_map(|a| a.strip_prefix("--scatter="))
+        .unwrap_or("poisson:5");
+    let mut split = value.splitn(2, ':');
+
+    match (
+        split.next(),
+        split.next().map(|v| match v.parse::<f64>() {
+            Ok(size) if size > 0.0 => size,
+            _ => panic!("Scatter spacing must be a positive number: {}", v),
+        }),
+    ) {
+        (Some("grid"), size) => scatter::Distribution::JitteredGrid {
+            cell: size.unwrap_or(5.0),
+        },
+        (_, size) => scatter::Distribution::PoissonDisk {
+            min_distance: size.unwrap_or(5.0),
+        },
+    }
+}
 
-        &mut lsystem
-            .translate(
-                Point(-10.0, -8.0, 16.0),
-                //Point(-10.0, 10.0, 30.0),
+fn generate_multiple_plants(args: &[String]) -> crate::scene::ObjectContainer {
+    let mut objs: scene::ObjectContainer = vec![Box::new(
+        plane! {Point(-10.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0).normalize();
+        <grid>(Color(124,252,0), Color(100,200,0), 10.0, 0.2, 1.0, 1.0)},
+    )];
+
+    // Extra species are given as `--species=path[:weight]`
+    let scatter = args
+        .iter()
+        .filter_map(|a| a.strip_prefix("--species="))
+        .map(scatter::Species::parse)
+        .fold(
+            scatter::Scatter::new(
+                Point(-10.0, 0.0, 24.0),
                 Vector::new(1.0, 0.0, 0.0).normalize(),
-                Vector::new(0.0, -1.0, 0.0).normalize(),
-                //Vector::new(1.0, -1.0, -1.0).normalize(),
-                0.3,
+                Vector::new(0.0, 12.0, 0.0),
+                Vector::new(0.0, 0.0, 10.0),
+            )
+            .with_distribution(parse_distribution(args))
+            .with_scale(0.6, 1.0)
+            .with_seed(
+                args.iter()
+                    .find_map(|a| a.strip_prefix("--seed="))
+                    .map_or(0, |s| s.parse::<u64>().unwrap()),
             )
-            .last_mut()
-            .unwrap()
-    });
+            .with_species(scatter::Species::parse(&args[2])),
+            |scatter, species| scatter.with_species(species),
+        );
 
-    objs.append({
-        let lsystem = lsystem::LSystem::from_file(&args[2]).unwrap().generate();
+    let scatter = match args.iter().find_map(|a| a.strip_prefix("--variants=")) {
+        Some(n) => scatter.with_variants(n.parse::<usize>().unwrap()),
+        None => scatter,
+    };
 
-        &mut lsystem
-            .translate(
-                Point(-10.0, 3.0, 21.0),
-                //Point(-10.0, 10.0, 30.0),
-                Vector::new(1.0, 0.0, 0.0).normalize(),
-                Vector::new(0.0, -1.0, 0.0).normalize(),
-                //Vector::new(1.0, -1.0, -1.0).normalize(),
-                0.4,
-            )
-            .last_mut()
-            .unwrap()
-    });
+    match scatter.populate() {
+        Ok(mut plants) => objs.append(&mut plants),
+        Err(e) => println!("Could not populate the forest: {}", e),
+    }
 
-    objs.append({
-        let lsystem = lsystem::LSystem::from_file(&args[2]).unwrap().generate();
+    objs
+}
 
-        &mut lsystem
-            .translate(
-                Point(-10.0, 3.0, 32.0),
-                //Point(-10.0, 10.0, 30.0),
-                Vector::new(1.0, 0.0, 0.0).normalize(),
-                Vector::new(0.0, -1.0, 0.0).normalize(),
-                //Vector::new(1.0, -1.0, -1.0).normalize(),
-                0.4,
+/// Resolution is given as `--size=<width>x<height>`
+fn parse_size(args: &[String]) -> (usize, usize) {
+    args.iter()
+        .find_map(|a| a.strip_prefix("--size="))
+        .map_or((900, 900), |s| {
+            let mut split = s.splitn(2, 'x');
+            (
+                split.next().unwrap().parse::<usize>().unwrap(),
+                split.next().unwrap().parse::<usize>().unwrap(),
             )
-            .last_mut()
-            .unwrap()
-    });
+        })
+}
 
-    objs
+fn load_scene_file(args: &[String]) -> std::io::Result<scene_file::SceneFile> {
+    match args.iter().find_map(|a| a.strip_prefix("--scene=")) {
+        Some(path) => scene_file::SceneFile::load(path),
+        None => Ok(Default::default()),
+    }
 }
 
-fn main() {
-    let args = std::env::args().collect::<Vec<String>>();
+const PLANT_POS: Point = Point(-10.0, 0.0, 20.0);
 
-    let (res_x, res_y) = (900, 900);
+/// Geometry of the plant at the center of the scene
+fn plant_geometry(lsystem: &lsystem::LSystem, time: f64) -> lsystem::LSGeometry {
+    lsystem.shapes_at(
+        time,
+        PLANT_POS,
+        Vector::new(1.0, 0.0, 0.0).normalize(),
+        Vector::new(0.0, -1.0, 0.0).normalize(),
+        PLANT_LENGTH,
+    )
+}
+
+fn ground() -> scene::ObjectContainer {
+    vec![Box::new(
+        disk! {Point(-10.0, 0.0, 20.0), Vector::new(1.0, 0.0, 0.0).normalize(); 10.0;
+        <checker>(Color(166,166,166), Color(140,140,140), 2.0, 1.0, 1.0)},
+    )]
+}
+
+/// Engine rendering `objects` at `width` x `height` pixels, with the camera,
+/// lights, props and rendering settings given by the command line and scene
+/// file, the props and environment map being read through `assets`
+fn build_engine(
+    args: &[String],
+    scene_file: &scene_file::SceneFile,
+    mut objects: scene::ObjectContainer,
+    (width, height): (usize, usize),
+    assets: &mut Assets,
+) -> engine::Engine {
     let cam = scene::Camera::new(
         Point(4.0, 0.0, -1.0),
         Point(0.0, 0.0, 20.0),
         Vector::new(1.0, 0.0, 0.0).normalize(),
         90.0,
-        90.0,
         1.0,
-        res_x,
-        res_y,
+        width,
+        height,
     );
-    let mut lights = scene::LightContainer::new();
-    let mut objs: scene::ObjectContainer = vec![
-        Box::new(
-            triangle! {Point(-10.0, 10.0, 30.0), Point(-10.0, 10.0, 10.0), Point(-10.0, -10.0, 30.0);
-            <uniform>(Color(166,166,166), 1.0, 1.0)},
-        ),
-        Box::new(
-            triangle! {Point(-10.0, -10.0, 10.0), Point(-10.0, 10.0, 10.0), Point(-10.0, -10.0, 30.0);
-            <uniform>(Color(166,166,166), 1.0, 1.0)},
+    let cam = match args.iter().find_map(|a| a.strip_prefix("--projection=")) {
+        Some(projection) => parse_projection(cam, projection),
+        None => cam,
+    };
+    // Depth of field is given as `--aperture=radius` and `--focus=distance`
+    let cam = match args.iter().find_map(|a| a.strip_prefix("--aperture=")) {
+        Some(aperture) => cam.with_lens(
+            aperture.parse::<f64>().unwrap(),
+            args.iter()
+                .find_map(|a| a.strip_prefix("--focus="))
+                .map(|f| f.parse::<f64>().unwrap()),
         ),
+        None => cam,
+    };
+    let mut cam = cam;
+
+    if let Some(track) = &scene_file.track {
+        cam.look(&track.sample(track.range().0));
+    }
+
+    let lights: scene::LightContainer = vec![
+        Box::new(scene::light::PointLight::new(
+            Point(6.0, -15.0, 12.0),
+            (1.0, 1.0, 1.0),
+        )),
+        //Box::new(scene::light::PointLight::new(
+        //Point(6.0, 5.0, 12.0),
+        //(1.0, 1.0, 1.0),
+        //)),
     ];
 
-    /*
-    objs.append({
-        let lsystem = lsystem::LSystem::from_file(&args[2]).unwrap().generate();
+    // Props are given as `--obj=path[:x,y,z[,scale[,dx,dy,dz]]]`, moving by
+    // (dx, dy, dz) during a frame
+    for prop in args.iter().filter_map(|a| a.strip_prefix("--obj=")) {
+        let mut split = prop.splitn(2, ':');
+        let path = split.next().unwrap();
+        let placement: Vec<f64> = split
+            .next()
+            .map(|p| p.split(',').map(|v| v.parse::<f64>().unwrap()).collect())
+            .unwrap_or_default();
+
+        match assets.props.load(path) {
+            Ok(props) if placement.len() >= 3 => {
+                let scale = placement.get(3).copied().unwrap_or(1.0);
+                let matrix = geometry::Matrix::translation(Vector::new(
+                    placement[0],
+                    placement[1],
+                    placement[2],
+                )) * geometry::Matrix::scaling(scale, scale, scale);
+
+                let prop = scene::Transformed::new(scene::Group::new(props)).with_transform(matrix);
+
+                objects.push(match placement[..] {
+                    [_, _, _, _, dx, dy, dz, ..] => Box::new(
+                        prop.with_motion(geometry::Matrix::translation(Vector::new(dx, dy, dz))),
+                    ),
+                    _ => Box::new(prop),
+                })
+            }
+            Ok(props) => objects.push(Box::new(scene::Group::new(props))),
+            Err(e) => println!("Could not load {}: {}", path, e),
+        }
+    }
 
-        &mut lsystem.translate(
-            Point(-10.0, 0.0, 20.0),
-            //Point(-10.0, 10.0, 30.0),
-            Vector::new(1.0, 0.0, 0.0).normalize(),
-            Vector::new(0.0, -1.0, 0.0).normalize(),
-            //Vector::new(1.0, -1.0, -1.0).normalize(),
-            0.5,
-            0.1,
-        )[args[3].parse::<usize>().unwrap()]
-    });
-    */
-    let plants = {
-        let lsystem = lsystem::LSystem::from_file(&args[2]).unwrap().generate();
-
-        &mut lsystem.translate(
-            Point(-10.0, 0.0, 20.0),
-            //Point(-10.0, 10.0, 30.0),
-            Vector::new(1.0, 0.0, 0.0).normalize(),
-            Vector::new(0.0, -1.0, 0.0).normalize(),
-            //Vector::new(1.0, -1.0, -1.0).normalize(),
-            0.5,
-        )
-    };
+    let scene = scene::Scene::new(cam, lights, objects)
+        .with_background(parse_background(args, &mut assets.textures));
 
-    lights.push(Box::new(scene::light::PointLight::new(
-        Point(6.0, -15.0, 12.0),
-        (1.0, 1.0, 1.0),
-    )));
-    //lights.push(Box::new(scene::light::PointLight::new(
-    //Point(6.0, 5.0, 12.0),
-    //(1.0, 1.0, 1.0),
-    //)));
+    let mut engine = engine::Engine::new(scene);
+    //let mut engine = engine::Engine::new(premade_scenes::scene1::get(width, height));
+    //let mut engine = engine::Engine::new(premade_scenes::scene2::get(width, height));
 
-    let is_gif = args[1].contains("gif");
-    let is_growth = args[1].contains("growth");
+    if let Some(samples) = args.iter().find_map(|a| a.strip_prefix("--samples=")) {
+        engine.set_samples(samples.parse::<usize>().unwrap());
+    }
 
-    let scene = scene::Scene::new(
-        cam,
-        lights,
-        if is_growth {
-            objs
-        } else {
-            plants.pop().unwrap()
-        },
-    );
+    if let Some(shutter) = args.iter().find_map(|a| a.strip_prefix("--shutter=")) {
+        engine.set_motion_blur(shutter.parse::<f64>().unwrap());
+    }
 
-    let mut engine = engine::Engine::new(scene);
-    //let mut engine = engine::Engine::new(premade_scenes::scene1::get(res_x, res_y));
-    //let mut engine = engine::Engine::new(premade_scenes::scene2::get(res_x, res_y));
+    // Post-processing effects follow those of the scene file, given as
+    // `--post=<effect>[:<parameter>...]` such as `--post=vignette:0.4`
+    let mut effects = scene_file.post.clone();
+    for effect in args.iter().filter_map(|a| a.strip_prefix("--post=")) {
+        let mut split = effect.split(':');
+        let name = split.next().unwrap();
+        let params: Vec<f64> = split.map(|p| p.parse::<f64>().unwrap()).collect();
+        effects.push(
+            post::Effect::parse(name, &params)
+                .unwrap_or_else(|| panic!("Invalid effect: {}", effect)),
+        );
+    }
+    engine.set_post_processing(effects);
+
+    // Denoising is enabled with `--denoise` or `--denoise=<iterations>`, the
+    // number of iterations being capped
+    if let Some(denoise) = args.iter().find(|a| a.starts_with("--denoise")) {
+        let defaults = image::DenoiseOptions::default();
+        engine.set_denoising(image::DenoiseOptions {
+            iterations: denoise
+                .strip_prefix("--denoise=")
+                .map_or(defaults.iterations, |n| n.parse::<usize>().unwrap())
+                .min(image::MAX_DENOISE_ITERATIONS),
+            ..defaults
+        });
+    }
 
     engine.set_diffuse();
     engine.set_specular();
-    engine.set_ambient((0.4, 0.4, 0.4));
+    if args.iter().any(|a| a == "--ao") {
+        engine.set_ambient_occlusion((0.4, 0.4, 0.4), 16, 5.0);
+    } else {
+        engine.set_ambient((0.4, 0.4, 0.4));
+    }
+    if args.iter().any(|a| a == "--ibl") {
+        engine.set_environment_lighting(1.0, 16);
+    }
     //engine.set_reflection();
     //engine.set_intersect();
 
-    if is_gif {
+    engine
+}
+
+/// Render the plant to `args[1]` again whenever the L-system, the scene file,
+/// a texture, a prop or the environment map changes, until interrupted. The
+/// L-system and scene file are only read again when they change themselves,
+/// and images and props when their files do. Loading errors are printed and
+/// fixed by the next change.
+fn watch(args: &[String]) {
+    let scene_path = args.iter().find_map(|a| a.strip_prefix("--scene="));
+    let mut watcher = watcher::Watcher::new(
+        std::iter::once(args[2].as_str())
+            .chain(scene_path)
+            .chain(input_files(args)),
+    );
+    let mut assets = Assets::default();
+    let mut geometry = None;
+    let mut scene_file = None;
+
+    loop {
+        if geometry.is_none() {
+            geometry = match lsystem::LSystem::from_file(&args[2]) {
+                Ok(lsystem) => Some(plant_geometry(&lsystem.generate(), f64::INFINITY)),
+                Err(e) => {
+                    println!("Could not load {}: {}", args[2], e);
+                    None
+                }
+            };
+        }
+        if scene_file.is_none() {
+            scene_file = match load_scene_file(args) {
+                Ok(scene_file) => Some(scene_file),
+                Err(e) => {
+                    println!("Could not load scene: {}", e);
+                    None
+                }
+            };
+        }
+
+        if let (Some(geometry), Some(scene_file)) = (&geometry, &scene_file) {
+            for material in geometry.materials.iter() {
+                if let lsystem::LSMaterial::Texture(path) = material {
+                    watcher.watch(path.as_str());
+                }
+            }
+
+            match geometry.to_objects_with(&mut assets.textures) {
+                Ok(objects) => {
+                    println!("Rendering image");
+                    let engine =
+                        build_engine(args, scene_file, objects, parse_size(args), &mut assets);
+                    save_image(&args[1], &engine.render(None));
+                }
+                Err(e) => println!("Could not load texture {}", e),
+            }
+            for path in assets.props.files() {
+                watcher.watch(path);
+            }
+        }
+
+        println!("Watching for changes");
+        let changed = loop {
+            let changed = watcher.changed_files();
+            if !changed.is_empty() {
+                break changed;
+            }
+            std::thread::sleep(WATCH_INTERVAL);
+        };
+        if changed.iter().any(|p| p == Path::new(&args[2])) {
+            geometry = None;
+        }
+        if scene_path.is_some_and(|s| changed.iter().any(|p| p == Path::new(s))) {
+            scene_file = None;
+        }
+    }
+}
+
+fn main() {
+    let mut args = std::env::args().collect::<Vec<String>>();
+
+    // `watch <output> <lsystem> [options]` renders again on every change of
+    // the input files, without needing a display
+    if args.get(1).is_some_and(|a| a == "watch") {
+        args.remove(1);
+        watch(&args);
+        return;
+    }
+
+    let (width, height) = parse_size(&args);
+
+    // With `--preview`, the plant is shown in a window instead, rendered again
+    // whenever the L-system or scene file changes
+    #[cfg(feature = "preview")]
+    if args.iter().any(|a| a == "--preview") {
+        let watcher = watcher::Watcher::new(
+            std::iter::once(args[2].as_str())
+                .chain(args.iter().filter_map(|a| a.strip_prefix("--scene=")))
+                .chain(input_files(&args)),
+        );
+        let mut assets = Assets::default();
+        let load = || {
+            let scene_file =
+                load_scene_file(&args).map_err(|e| format!("Could not load scene: {}", e))?;
+            let lsystem = lsystem::LSystem::from_file(&args[2])
+                .map_err(|e| format!("Could not load {}: {}", args[2], e))?;
+            let geometry = plant_geometry(&lsystem.generate(), f64::INFINITY);
+            Ok(build_engine(
+                &args,
+                &scene_file,
+                geometry.to_objects(),
+                (width, height),
+                &mut assets,
+            ))
+        };
+
+        if let Err(e) = preview::run(width, height, watcher, load) {
+            println!("Could not open the preview window: {}", e);
+        }
+        return;
+    }
+
+    let scene_file =
+        load_scene_file(&args).unwrap_or_else(|e| panic!("Could not load scene: {}", e));
+
+    // Iterations are rendered side by side with `--iterations`, or one per
+    // frame in a GIF
+    let is_history = args.iter().any(|a| a == "--iterations");
+    let lsystem = {
+        let lsystem = lsystem::LSystem::from_file(&args[2]).unwrap();
+        if is_history {
+            lsystem.with_history()
+        } else {
+            lsystem
+        }
+    }
+    .generate();
+    let geometry = plant_geometry(&lsystem, f64::INFINITY);
+
+    if args[1].ends_with(".obj") || args[1].ends_with(".ply") {
+        let sides = args
+            .iter()
+            .find_map(|a| a.strip_prefix("--sides="))
+            .map_or(EXPORT_SIDES, |s| s.parse::<usize>().unwrap());
+
+        println!("Exporting geometry");
+        let res = if args[1].ends_with(".obj") {
+            export::to_obj(&geometry, &args[1], sides)
+        } else {
+            export::to_ply(&geometry, &args[1], sides)
+        };
+        match res {
+            Ok(_) => println!("Success!"),
+            Err(e) => println!("Could not write {}: {}", args[1], e),
+        }
+        return;
+    }
+
+    // Animations are written as GIF, APNG, image sequence or to an encoder
+    let is_animation = args[1].contains("gif")
+        || args[1].ends_with(".apng")
+        || args
+            .iter()
+            .any(|a| a == "--sequence" || a.starts_with("--pipe="));
+    let is_growth = args[1].contains("growth");
+
+    let is_forest = args[1].contains("forest");
+
+    let objects = if is_growth || is_history {
+        ground()
+    } else if is_forest {
+        generate_multiple_plants(&args)
+    } else {
+        geometry.to_objects()
+    };
+
+    let mut engine = build_engine(
+        &args,
+        &scene_file,
+        objects,
+        (width, height),
+        &mut Assets::default(),
+    );
+    let nb_lights = engine.nb_lights();
+
+    if is_history {
+        let iterations: Vec<lsystem::LSystem> = (0..=lsystem.iteration())
+            .map(|n| lsystem.at_iteration(n).unwrap())
+            .collect();
+        let nb = iterations.len();
+        let mut build = |t: f64| {
+            plant_geometry(
+                &iterations[(t * (nb - 1) as f64).round() as usize],
+                f64::INFINITY,
+            )
+            .to_objects()
+        };
+
+        if is_animation {
+            println!("Rendering iterations");
+            let track = scene_file.track.as_ref();
+            let mut sink = frame_sink(&args, &args[1], nb);
+            let res =
+                engine.render_growth(&mut camera_motion(track, nb), nb, &mut build, &mut *sink);
+            report(&args[1], res);
+        } else {
+            println!("Rendering contact sheet");
+            let mut frames = Vec::new();
+            engine
+                .render_growth(&mut |_| (), nb, &mut build, &mut frames)
+                .unwrap();
+            let columns = (nb as f64).sqrt().ceil() as usize;
+            save_image(&args[1], &Image::tile(&frames, columns));
+        }
+        return;
+    }
+
+    if is_animation {
+        let track = scene_file.track.as_ref();
+
         if is_growth {
             println!("Rendering growth");
+            let nb_frames = scene_file.frames.unwrap_or(36);
+            let duration = lsystem.growth_duration();
+            let mut sink = frame_sink(&args, &args[1], nb_frames);
             let res = engine.render_growth(
-                &mut |c| c.rotate_around_center_of_view(10.0f64.to_radians()),
-                //&mut |_| (),
-                9,
-                plants,
+                &mut camera_motion(track, nb_frames),
+                nb_frames,
+                &mut |t| plant_geometry(&lsystem, t * duration).to_objects(),
+                &mut *sink,
             );
-            save_gif(&args[1], &res);
+            report(&args[1], res);
         } else {
             println!("Rendering travelling");
-            let res = engine.travelling(
-                &mut |c| c.rotate_around_center_of_view(10.0f64.to_radians()),
-                36,
-            );
-            save_gif(&args[1], &res);
+            let nb_frames = scene_file.frames.unwrap_or(36);
+            let mut sink = frame_sink(&args, &args[1], nb_frames);
+            let res =
+                engine.travelling(&mut camera_motion(track, nb_frames), nb_frames, &mut *sink);
+            report(&args[1], res);
         }
     } else {
+        // Only part of the image is traced with `--region=x,y,width,height`, or
+        // with `--tile=size:index` for the index-th square tile when splitting
+        // the render between machines. It is saved alone with `--crop` or in a
+        // blank image otherwise.
+        let region = args
+            .iter()
+            .find_map(|a| a.strip_prefix("--region="))
+            .map(|r| {
+                let v: Vec<usize> = r.split(',').map(|v| v.parse::<usize>().unwrap()).collect();
+                image::Region::new(v[0], v[1], v[2], v[3])
+            })
+            .or_else(|| {
+                args.iter()
+                    .find_map(|a| a.strip_prefix("--tile="))
+                    .map(|t| {
+                        let mut split = t.splitn(2, ':');
+                        let size = split.next().unwrap().parse::<usize>().unwrap();
+                        let index = split.next().unwrap().parse::<usize>().unwrap();
+                        let tiles = image::Region::tiles(width, height, size);
+                        tiles.get(index).copied().unwrap_or_else(|| {
+                            println!(
+                                "Invalid tile index {}: the image has {} tiles of {} pixels",
+                                index,
+                                tiles.len(),
+                                size
+                            );
+                            std::process::exit(1)
+                        })
+                    })
+            });
+
+        // Auxiliary buffers are given as `--aov=color,depth,normal,id,albedo,shadow,lights`
+        // and saved next to the image, `out.png` giving `out_depth.png`...
+        let aovs: Vec<engine::Aov> =
+            args.iter()
+                .find_map(|a| a.strip_prefix("--aov="))
+                .map_or(Vec::new(), |list| {
+                    list.split(',')
+                        .flat_map(|name| {
+                            engine::Aov::parse(name, nb_lights)
+                                .unwrap_or_else(|| panic!("Unknown AOV: {}", name))
+                        })
+                        .collect()
+                });
+        let crop = args.iter().any(|a| a == "--crop");
+
         println!("Rendering image");
-        let image = engine.render();
+        if aovs.is_empty() {
+            let image = match region {
+                Some(region) if crop => engine.render_cropped(region),
+                region => engine.render(region),
+            };
 
-        save_image(&args[1], &image);
+            save_image(&args[1], &image);
+        } else {
+            let full = image::Region::new(0, 0, width, height);
+            let (image, buffers) = engine.render_aovs(region.unwrap_or(full), &aovs);
+
+            // Put the region back in a blank image unless cropping
+            let uncrop = |image: Image| match region {
+                Some(region) if !crop => {
+                    let mut res = Image::new(width, height);
+                    res.paste(&image, region.x, region.y);
+                    res
+                }
+                _ => image,
+            };
+
+            save_image(&args[1], &uncrop(image));
+            let path = Path::new(&args[1]);
+            for (aov, buffer) in aovs.iter().zip(buffers.iter()) {
+                let aov_path = path.with_file_name(format!(
+                    "{}_{}.png",
+                    path.file_stem().unwrap_or_default().to_string_lossy(),
+                    aov.name()
+                ));
+                save_image(&aov_path.to_string_lossy(), &uncrop(aov.to_image(buffer)));
+            }
+        }
     }
 }
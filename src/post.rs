@@ -0,0 +1,279 @@
+//! Post-processing of the rendered colors, applied as a stack of effects
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::image::FloatImage;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    /// Brighten or darken by a number of stops
+    Exposure(f64),
+    /// Make a light of the given temperature in Kelvin look white, lower
+    /// temperatures giving cooler images
+    WhiteBalance(f64),
+    /// Scale the distance of colors to gray, 0 giving a black and white image
+    Saturation(f64),
+    /// Glow around the parts brighter than `threshold`, blurred over `radius`
+    /// pixels
+    Bloom {
+        threshold: f64,
+        radius: usize,
+        strength: f64,
+    },
+    /// Darken the corners
+    Vignette(f64),
+    /// Random noise of the given amplitude, as film grain, drawn from the
+    /// position of the pixel in the frame
+    Grain(f64),
+}
+
+fn luminance((r, g, b): (f64, f64, f64)) -> f64 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Color of a black body at `kelvin` degrees, after Tanner Helland's fit
+fn black_body(kelvin: f64) -> (f64, f64, f64) {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+    let r = if t <= 66.0 {
+        255.0
+    } else {
+        329.698727446 * (t - 60.0).powf(-0.1332047592)
+    };
+    let g = if t <= 66.0 {
+        99.4708025861 * t.ln() - 161.1195681661
+    } else {
+        288.1221695283 * (t - 60.0).powf(-0.0755148492)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.5177312231 * (t - 10.0).ln() - 305.0447927307
+    };
+
+    let channel = |v: f64| v.clamp(1.0, 255.0) / 255.0;
+    (channel(r), channel(g), channel(b))
+}
+
+/// Repeated horizontal and vertical box blurs, close to a gaussian blur
+fn blur(image: &FloatImage, radius: usize) -> FloatImage {
+    let (width, height) = image.size();
+    let mut res = image.clone();
+
+    for _ in 0..3 {
+        for &horizontal in [true, false].iter() {
+            let input = res.clone();
+            for y in 0..height {
+                for x in 0..width {
+                    let (mut sum, mut n) = ((0.0, 0.0, 0.0), 0.0);
+                    let (pos, len) = if horizontal { (x, width) } else { (y, height) };
+
+                    for i in pos.saturating_sub(radius)..(pos + radius + 1).min(len) {
+                        let (r, g, b) = if horizontal {
+                            input.get(i, y)
+                        } else {
+                            input.get(x, i)
+                        };
+                        sum = (sum.0 + r, sum.1 + g, sum.2 + b);
+                        n += 1.0;
+                    }
+                    res.set(x, y, (sum.0 / n, sum.1 / n, sum.2 / n));
+                }
+            }
+        }
+    }
+
+    res
+}
+
+impl Effect {
+    /// Parse an effect from its name and parameters, as in `bloom 0.8 8 0.5`
+    pub fn parse(name: &str, params: &[f64]) -> Option<Effect> {
+        Some(match (name, params) {
+            ("exposure", [stops]) => Effect::Exposure(*stops),
+            ("white-balance", [kelvin]) => Effect::WhiteBalance(*kelvin),
+            ("saturation", [scale]) => Effect::Saturation(*scale),
+            ("bloom", [threshold, radius, strength]) => Effect::Bloom {
+                threshold: *threshold,
+                radius: *radius as usize,
+                strength: *strength,
+            },
+            ("vignette", [strength]) => Effect::Vignette(*strength),
+            ("grain", [amplitude]) => Effect::Grain(*amplitude),
+            _ => return None,
+        })
+    }
+
+    /// Distance in pixels up to which the effect reads around a pixel
+    pub fn reach(&self) -> usize {
+        match self {
+            // One box blur per pass, three passes
+            Effect::Bloom { radius, .. } => 3 * radius,
+            _ => 0,
+        }
+    }
+
+    /// Apply the effect to `image`, whose top left corner is at `offset` in a
+    /// frame of size `frame`
+    pub fn apply(&self, image: &mut FloatImage, offset: (usize, usize), frame: (usize, usize)) {
+        let (width, height) = image.size();
+
+        let blurred = match self {
+            Effect::Bloom {
+                threshold, radius, ..
+            } => {
+                let mut bright = image.clone();
+                for y in 0..height {
+                    for x in 0..width {
+                        let (r, g, b) = image.get(x, y);
+                        let excess = |v: f64| (v - threshold).max(0.0);
+                        bright.set(x, y, (excess(r), excess(g), excess(b)));
+                    }
+                }
+                Some(blur(&bright, *radius))
+            }
+            _ => None,
+        };
+
+        let white = black_body(6500.0);
+        let (cx, cy) = (frame.0 as f64 / 2.0, frame.1 as f64 / 2.0);
+        let half_diagonal = (cx * cx + cy * cy).sqrt().max(1.0);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (r, g, b) = image.get(x, y);
+
+                let color = match self {
+                    Effect::Exposure(stops) => {
+                        let k = 2f64.powf(*stops);
+                        (r * k, g * k, b * k)
+                    }
+                    Effect::WhiteBalance(kelvin) => {
+                        let light = black_body(*kelvin);
+                        (
+                            r * white.0 / light.0,
+                            g * white.1 / light.1,
+                            b * white.2 / light.2,
+                        )
+                    }
+                    Effect::Saturation(scale) => {
+                        let l = luminance((r, g, b));
+                        (
+                            l + (r - l) * scale,
+                            l + (g - l) * scale,
+                            l + (b - l) * scale,
+                        )
+                    }
+                    Effect::Bloom { strength, .. } => {
+                        let (br, bg, bb) = blurred.as_ref().unwrap().get(x, y);
+                        (r + br * strength, g + bg * strength, b + bb * strength)
+                    }
+                    Effect::Vignette(strength) => {
+                        let (dx, dy) = (
+                            (offset.0 + x) as f64 + 0.5 - cx,
+                            (offset.1 + y) as f64 + 0.5 - cy,
+                        );
+                        let d = (dx * dx + dy * dy).sqrt() / half_diagonal;
+                        let k = (1.0 - strength * d * d).max(0.0);
+                        (r * k, g * k, b * k)
+                    }
+                    Effect::Grain(amplitude) => {
+                        let (fx, fy) = (offset.0 + x, offset.1 + y);
+                        let mut rng = StdRng::seed_from_u64((fy * frame.0 + fx) as u64);
+                        let noise = (rng.gen::<f64>() - 0.5) * amplitude;
+                        (r + noise, g + noise, b + noise)
+                    }
+                };
+
+                image.set(x, y, color);
+            }
+        }
+    }
+}
+
+/// Apply the effects in order to `image`, placed at `offset` in a frame of
+/// size `frame`
+pub fn apply(
+    effects: &[Effect],
+    image: &FloatImage,
+    offset: (usize, usize),
+    frame: (usize, usize),
+) -> FloatImage {
+    let mut res = image.clone();
+    for effect in effects.iter() {
+        effect.apply(&mut res, offset, frame);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uniform(color: (f64, f64, f64)) -> FloatImage {
+        let mut image = FloatImage::new(9, 9);
+        for y in 0..9 {
+            for x in 0..9 {
+                image.set(x, y, color);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn color_grading() {
+        let image = uniform((0.2, 0.4, 0.6));
+
+        let res = apply(&[Effect::Exposure(1.0)], &image, (0, 0), (9, 9));
+        assert_eq!(res.get(4, 4), (0.4, 0.8, 1.2));
+
+        let res = apply(&[Effect::Saturation(0.0)], &image, (0, 0), (9, 9));
+        let (r, g, b) = res.get(4, 4);
+        assert!((r - g).abs() < 1e-9 && (g - b).abs() < 1e-9);
+
+        let (r, _, b) = apply(&[Effect::WhiteBalance(3000.0)], &image, (0, 0), (9, 9)).get(4, 4);
+        assert!(r <= 0.2 && b > 0.6);
+    }
+
+    #[test]
+    fn bloom_and_vignette() {
+        let mut image = uniform((0.5, 0.5, 0.5));
+        image.set(4, 4, (4.0, 4.0, 4.0));
+
+        let bloom = Effect::parse("bloom", &[1.0, 2.0, 1.0]).unwrap();
+        let res = apply(&[bloom], &image, (0, 0), (9, 9));
+        assert!(res.get(5, 4).0 > res.get(8, 8).0);
+        assert!(res.get(8, 8).0 >= 0.5);
+
+        let res = apply(
+            &[Effect::Vignette(0.5)],
+            &uniform((0.5, 0.5, 0.5)),
+            (0, 0),
+            (9, 9),
+        );
+        assert!(res.get(0, 0).0 < res.get(4, 4).0);
+
+        // A tile is darkened as the same pixels of the whole frame
+        let tile = uniform((0.5, 0.5, 0.5)).crop(crate::image::Region::new(6, 6, 3, 3));
+        let tile = apply(&[Effect::Vignette(0.5)], &tile, (6, 6), (9, 9));
+        assert_eq!(tile.get(2, 2), res.get(8, 8));
+    }
+
+    #[test]
+    fn grain() {
+        let grain = [Effect::Grain(0.2)];
+        let res = apply(&grain, &uniform((0.5, 0.5, 0.5)), (0, 0), (9, 9));
+        assert!(res.values().any(|&v| v != res.get(0, 0)));
+
+        // A tile gets the same noise as the same pixels of the whole frame
+        let tile = uniform((0.5, 0.5, 0.5)).crop(crate::image::Region::new(3, 6, 4, 3));
+        let tile = apply(&grain, &tile, (3, 6), (9, 9));
+        for y in 0..3 {
+            for x in 0..4 {
+                assert_eq!(tile.get(x, y), res.get(3 + x, 6 + y));
+            }
+        }
+    }
+}
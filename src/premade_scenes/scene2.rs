@@ -1,15 +1,14 @@
 use crate::*;
 
-pub fn get(res_x: usize, res_y: usize) -> scene::Scene {
+pub fn get(width: usize, height: usize) -> scene::Scene {
     let cam = scene::Camera::new(
         Point(0.0, 0.0, 0.0),
         Point(0.0, 0.0, 8.0),
         Vector::new(1.0, 0.0, 0.0).normalize(),
         90.0,
-        90.0,
         2.0,
-        res_x,
-        res_y,
+        width,
+        height,
     );
 
     let mut lights = scene::LightContainer::new();
@@ -0,0 +1,69 @@
+//! Window showing the render while it refines, built with the `preview`
+//! feature
+
+use minifb::{Key, Window, WindowOptions};
+
+use crate::{common::Color, engine::Engine, image::Image, watcher::Watcher};
+
+/// Size of the pixels of each pass compared to the final ones
+const PASSES: [usize; 4] = [8, 4, 2, 1];
+
+/// Copy the image in the window buffer, scaled up to fill it
+fn blit(image: &Image, buffer: &mut [u32], width: usize, height: usize) {
+    let (image_width, image_height) = image.size();
+
+    for y in 0..height {
+        let row = &image[(y * image_height / height).min(image_height - 1)];
+        for x in 0..width {
+            let Color(r, g, b) = row[(x * image_width / width).min(image_width - 1)];
+            buffer[y * width + x] = (r as u32) << 16 | (g as u32) << 8 | b as u32;
+        }
+    }
+}
+
+/// Show the scene in a `width` x `height` window until it is closed or
+/// Escape is pressed
+///
+/// `load` builds an engine of the files the scene comes from, whose errors are
+/// printed. Each load is rendered at increasing resolutions, starting over
+/// with a new load as soon as one of the watched files changes.
+pub fn run<L>(width: usize, height: usize, mut watcher: Watcher, mut load: L) -> minifb::Result<()>
+where
+    L: FnMut() -> Result<Engine, String>,
+{
+    let mut window = Window::new("isim-raytracer", width, height, WindowOptions::default())?;
+    window.set_target_fps(30);
+    let mut buffer = vec![0; width * height];
+
+    let is_open = |window: &Window| window.is_open() && !window.is_key_down(Key::Escape);
+
+    'reload: while is_open(&window) {
+        match load() {
+            Ok(mut engine) => {
+                for &scale in PASSES.iter() {
+                    engine.set_resolution((width / scale).max(1), (height / scale).max(1));
+                    blit(&engine.render(None), &mut buffer, width, height);
+
+                    window.update_with_buffer(&buffer, width, height)?;
+                    if !is_open(&window) {
+                        return Ok(());
+                    }
+                    if !watcher.changed_files().is_empty() {
+                        continue 'reload;
+                    }
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+
+        // Keep the window alive until something changes
+        while watcher.changed_files().is_empty() {
+            window.update_with_buffer(&buffer, width, height)?;
+            if !is_open(&window) {
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,374 @@
+use std::rc::Rc;
+
+use rand::distributions::{Distribution as _, WeightedIndex};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::common::{Point, ORIGIN};
+use crate::geometry::{NormalVector, Vector};
+use crate::lsystem::{LSystem, PLANT_LENGTH};
+use crate::scene::{Group, Instance, ObjectContainer};
+
+/// Number of random candidates tried around each point for Poisson-disk sampling
+const POISSON_CANDIDATES: usize = 30;
+
+/// How positions are laid out over the region, no position being given
+/// without a positive spacing
+#[derive(Debug, Clone, Copy)]
+pub enum Distribution {
+    /// Points no closer than `min_distance` from each other
+    PoissonDisk { min_distance: f64 },
+    /// One point per cell of size `cell`, randomly offset within it
+    JitteredGrid { cell: f64 },
+}
+
+/// A L-System file and how often it is picked relatively to the others
+#[derive(Debug, Clone)]
+pub struct Species {
+    pub path: String,
+    pub weight: f64,
+}
+
+impl Species {
+    /// Parse a species given as `path[:weight]`
+    pub fn parse(value: &str) -> Species {
+        let mut split = value.rsplitn(2, ':');
+        let last = split.next().unwrap();
+
+        match (split.next(), last.parse::<f64>()) {
+            (Some(path), Ok(weight)) => Species {
+                path: path.to_string(),
+                weight,
+            },
+            _ => Species {
+                path: value.to_string(),
+                weight: 1.0,
+            },
+        }
+    }
+}
+
+/// Scatter plants over a rectangular ground region centered on `center` and
+/// spanning `u` and `v` on each side
+#[derive(Debug, Clone)]
+pub struct Scatter {
+    center: Point,
+    up: NormalVector,
+    u: Vector,
+    v: Vector,
+    distribution: Distribution,
+    scale: (f64, f64),
+    variants: Option<usize>,
+    seed: u64,
+    species: Vec<Species>,
+}
+
+impl Scatter {
+    pub fn new(center: Point, up: NormalVector, u: Vector, v: Vector) -> Scatter {
+        Scatter {
+            center,
+            up,
+            u,
+            v,
+            distribution: Distribution::PoissonDisk { min_distance: 1.0 },
+            scale: (1.0, 1.0),
+            variants: None,
+            seed: 0,
+            species: vec![],
+        }
+    }
+
+    pub fn with_distribution(self, distribution: Distribution) -> Scatter {
+        Scatter {
+            distribution,
+            ..self
+        }
+    }
+
+    pub fn with_scale(self, min: f64, max: f64) -> Scatter {
+        Scatter {
+            scale: (min, max),
+            ..self
+        }
+    }
+
+    /// Limit the number of distinct plants generated per species, instances
+    /// then share them
+    pub fn with_variants(self, variants: usize) -> Scatter {
+        Scatter {
+            variants: Some(variants.max(1)),
+            ..self
+        }
+    }
+
+    pub fn with_seed(self, seed: u64) -> Scatter {
+        Scatter { seed, ..self }
+    }
+
+    pub fn with_species(mut self, species: Species) -> Scatter {
+        self.species.push(species);
+        self
+    }
+
+    /// Positions of the plants on the ground
+    pub fn positions(&self) -> Vec<Point> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        self.sample(&mut rng)
+    }
+
+    fn sample<R: Rng>(&self, rng: &mut R) -> Vec<Point> {
+        let (w, h) = (self.u.norm(), self.v.norm());
+
+        let points = match self.distribution {
+            Distribution::PoissonDisk { min_distance } if min_distance > 0.0 => {
+                poisson_disk(2.0 * w, 2.0 * h, min_distance, rng)
+            }
+            Distribution::JitteredGrid { cell } if cell > 0.0 => {
+                jittered_grid(2.0 * w, 2.0 * h, cell, rng)
+            }
+            _ => Vec::new(),
+        };
+
+        points
+            .iter()
+            .map(|&(s, t)| {
+                (Vector::from(ORIGIN, self.center)
+                    + (s / w - 1.0) * self.u
+                    + (t / h - 1.0) * self.v)
+                    .to_point()
+            })
+            .collect()
+    }
+
+    /// Generate the plants and place them over the region
+    pub fn populate(&self) -> std::io::Result<ObjectContainer> {
+        let positions = self.positions();
+        // Species, seeds and placements are drawn independently from positions
+        let mut rng = StdRng::seed_from_u64(!self.seed);
+
+        let lsystems = self
+            .species
+            .iter()
+            .map(|s| LSystem::from_file(&s.path))
+            .collect::<std::io::Result<Vec<LSystem>>>()?;
+
+        let weights = WeightedIndex::new(self.species.iter().map(|s| s.weight))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+
+        // Any vector orthogonal to up will do, instances are rotated anyway
+        let right = {
+            let up = self.up.vector();
+            let other = if up.x.abs() < 0.9 {
+                Vector::new(1.0, 0.0, 0.0)
+            } else {
+                Vector::new(0.0, 1.0, 0.0)
+            };
+            Vector::cross_product(&up, &other).normalize()
+        };
+
+        let mut plants: Vec<Vec<Option<Rc<ObjectContainer>>>> = self
+            .species
+            .iter()
+            .map(|_| vec![None; self.variants.unwrap_or(0)])
+            .collect();
+
+        let mut res = ObjectContainer::new();
+
+        for (i, pos) in positions.iter().enumerate() {
+            let species = weights.sample(&mut rng);
+            let seed = rng.gen::<u64>();
+
+            println!("Generating plant {}/{}", i + 1, positions.len());
+            let generate = || {
+                Rc::new(lsystems[species].clone().generate_seeded(seed).translate(
+                    ORIGIN,
+                    self.up,
+                    right,
+                    PLANT_LENGTH,
+                ))
+            };
+
+            let plant = match self.variants {
+                Some(n) => plants[species][(seed % n as u64) as usize]
+                    .get_or_insert_with(generate)
+                    .clone(),
+                None => generate(),
+            };
+
+            let angle = rng.gen_range(0.0..2.0 * std::f64::consts::PI);
+            let scale = if self.scale.0 < self.scale.1 {
+                rng.gen_range(self.scale.0..self.scale.1)
+            } else {
+                self.scale.0
+            };
+
+            res.push(Box::new(
+                Instance::new(Group::new(plant))
+                    .with_rotation(self.up, angle)
+                    .with_scale(scale)
+                    .with_translation(Vector::from(ORIGIN, *pos)),
+            ));
+        }
+
+        Ok(res)
+    }
+}
+
+/// Bridson's algorithm over `[0, width] x [0, height]`
+fn poisson_disk<R: Rng>(width: f64, height: f64, radius: f64, rng: &mut R) -> Vec<(f64, f64)> {
+    let cell = radius / std::f64::consts::SQRT_2;
+    let (cols, rows) = (
+        (width / cell).ceil().max(1.0) as usize,
+        (height / cell).ceil().max(1.0) as usize,
+    );
+
+    let cell_of = |(x, y): (f64, f64)| {
+        (
+            ((x / cell) as usize).min(cols - 1),
+            ((y / cell) as usize).min(rows - 1),
+        )
+    };
+
+    let mut grid: Vec<Option<usize>> = vec![None; cols * rows];
+    let mut points = vec![(rng.gen_range(0.0..width), rng.gen_range(0.0..height))];
+    let mut active = vec![0];
+
+    let (cx, cy) = cell_of(points[0]);
+    grid[cy * cols + cx] = Some(0);
+
+    while !active.is_empty() {
+        let idx = rng.gen_range(0..active.len());
+        let (x, y) = points[active[idx]];
+
+        let found = (0..POISSON_CANDIDATES).find_map(|_| {
+            let angle = rng.gen_range(0.0..2.0 * std::f64::consts::PI);
+            let dist = rng.gen_range(radius..2.0 * radius);
+            let p = (x + dist * angle.cos(), y + dist * angle.sin());
+
+            if p.0 < 0.0 || p.0 >= width || p.1 < 0.0 || p.1 >= height {
+                return None;
+            }
+
+            let (cx, cy) = cell_of(p);
+            let far_enough = (cy.saturating_sub(2)..(cy + 3).min(rows)).all(|j| {
+                (cx.saturating_sub(2)..(cx + 3).min(cols)).all(|i| match grid[j * cols + i] {
+                    Some(n) => {
+                        let (qx, qy) = points[n];
+                        (p.0 - qx).powi(2) + (p.1 - qy).powi(2) >= radius * radius
+                    }
+                    None => true,
+                })
+            });
+
+            if far_enough {
+                Some((p, cx, cy))
+            } else {
+                None
+            }
+        });
+
+        match found {
+            Some((p, cx, cy)) => {
+                grid[cy * cols + cx] = Some(points.len());
+                active.push(points.len());
+                points.push(p);
+            }
+            None => {
+                active.swap_remove(idx);
+            }
+        }
+    }
+
+    points
+}
+
+fn jittered_grid<R: Rng>(width: f64, height: f64, cell: f64, rng: &mut R) -> Vec<(f64, f64)> {
+    let (cols, rows) = (
+        (width / cell).floor().max(1.0) as usize,
+        (height / cell).floor().max(1.0) as usize,
+    );
+    let (cw, ch) = (width / cols as f64, height / rows as f64);
+
+    (0..rows)
+        .flat_map(|j| (0..cols).map(move |i| (i, j)))
+        .map(|(i, j)| {
+            (
+                (i as f64 + rng.gen::<f64>()) * cw,
+                (j as f64 + rng.gen::<f64>()) * ch,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region() -> Scatter {
+        Scatter::new(
+            Point(-10.0, 0.0, 20.0),
+            Vector::new(1.0, 0.0, 0.0).normalize(),
+            Vector::new(0.0, 10.0, 0.0),
+            Vector::new(0.0, 0.0, 5.0),
+        )
+    }
+
+    #[test]
+    fn poisson_disk_spacing() {
+        let points = region()
+            .with_distribution(Distribution::PoissonDisk { min_distance: 2.0 })
+            .positions();
+
+        assert!(points.len() > 10);
+        for (i, a) in points.iter().enumerate() {
+            assert!((a.0 + 10.0).abs() < 1e-9);
+            assert!(a.1 >= -10.0 && a.1 <= 10.0 && a.2 >= 15.0 && a.2 <= 25.0);
+            for b in points.iter().skip(i + 1) {
+                assert!(Vector::from(*a, *b).norm() >= 2.0 - 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn non_positive_spacing() {
+        for distribution in [
+            Distribution::PoissonDisk { min_distance: 0.0 },
+            Distribution::JitteredGrid { cell: -1.0 },
+        ] {
+            assert!(region()
+                .with_distribution(distribution)
+                .positions()
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn jittered_grid_cells() {
+        let points = region()
+            .with_distribution(Distribution::JitteredGrid { cell: 2.5 })
+            .positions();
+
+        assert_eq!(points.len(), 8 * 4);
+    }
+
+    #[test]
+    fn seeded_positions() {
+        let a = region().with_seed(42).positions();
+        let b = region().with_seed(42).positions();
+        let c = region().with_seed(43).positions();
+
+        assert_eq!(a.len(), b.len());
+        assert!(a.iter().zip(&b).all(|(p, q)| p.1 == q.1 && p.2 == q.2));
+        assert!(a.len() != c.len() || a.iter().zip(&c).any(|(p, q)| p.1 != q.1));
+    }
+
+    #[test]
+    fn parse_species() {
+        let s = Species::parse("trees/oak.l3d:2.5");
+        assert_eq!(s.path, "trees/oak.l3d");
+        assert_eq!(s.weight, 2.5);
+
+        let s = Species::parse("trees/oak.l3d");
+        assert_eq!(s.path, "trees/oak.l3d");
+        assert_eq!(s.weight, 1.0);
+    }
+}
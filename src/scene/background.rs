@@ -0,0 +1,112 @@
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+use imagelib::RgbImage;
+
+use crate::common::*;
+
+/// What a ray sees when it does not hit any object
+pub enum Background {
+    Solid(Color),
+    /// Vertical gradient from `bottom` (looking down) to `top` (looking up)
+    Gradient {
+        up: NormalVector,
+        top: Color,
+        bottom: Color,
+    },
+    /// Analytic daylight sky lit by a sun in the `sun` direction
+    Sky {
+        up: NormalVector,
+        sun: NormalVector,
+    },
+    /// Equirectangular environment map
+    Environment {
+        buffer: Rc<RgbImage>,
+        up: NormalVector,
+        forward: NormalVector,
+    },
+}
+
+fn mix(a: (f64, f64, f64), b: (f64, f64, f64), t: f64) -> (f64, f64, f64) {
+    (
+        a.0 + (b.0 - a.0) * t,
+        a.1 + (b.1 - a.1) * t,
+        a.2 + (b.2 - a.2) * t,
+    )
+}
+
+fn to_color((r, g, b): (f64, f64, f64)) -> Color {
+    let clamp = |v: f64| v.clamp(0.0, 255.0) as u8;
+    Color(clamp(r), clamp(g), clamp(b))
+}
+
+fn from_color(Color(r, g, b): Color) -> (f64, f64, f64) {
+    (r as f64, g as f64, b as f64)
+}
+
+impl Background {
+    /// Environment map of an image already loaded
+    pub fn from_image(buffer: Rc<RgbImage>, up: NormalVector, forward: NormalVector) -> Background {
+        Background::Environment {
+            buffer,
+            up,
+            forward,
+        }
+    }
+
+    pub fn sample(&self, direction: &NormalVector) -> Color {
+        match self {
+            Background::Solid(c) => *c,
+            Background::Gradient { up, top, bottom } => {
+                let t = (NormalVector::dot_product(up, direction) + 1.0) / 2.0;
+                to_color(mix(from_color(*bottom), from_color(*top), t))
+            }
+            Background::Sky { up, sun } => Background::sky(up, sun, direction),
+            Background::Environment {
+                buffer,
+                up,
+                forward,
+            } => {
+                let d = direction.vector();
+                let f = forward.vector();
+                let right = Vector::cross_product(&f, &up.vector());
+
+                let phi = Vector::dot_product(&d, &right).atan2(Vector::dot_product(&d, &f));
+                let theta = NormalVector::dot_product(up, direction)
+                    .clamp(-1.0, 1.0)
+                    .acos();
+
+                let u = 0.5 + phi / (2.0 * PI);
+                let v = theta / PI;
+
+                let i = ((u * buffer.width() as f64) as u32).min(buffer.width() - 1);
+                let j = ((v * buffer.height() as f64) as u32).min(buffer.height() - 1);
+
+                let pixel = buffer.get_pixel(i, j);
+                Color(pixel.0[0], pixel.0[1], pixel.0[2])
+            }
+        }
+    }
+
+    fn sky(up: &NormalVector, sun: &NormalVector, direction: &NormalVector) -> Color {
+        const ZENITH: (f64, f64, f64) = (60.0, 110.0, 200.0);
+        const HORIZON: (f64, f64, f64) = (190.0, 215.0, 235.0);
+        const GROUND: (f64, f64, f64) = (90.0, 85.0, 80.0);
+        const SUN: (f64, f64, f64) = (255.0, 245.0, 220.0);
+
+        let elevation = NormalVector::dot_product(up, direction);
+        if elevation < 0.0 {
+            // Fade quickly from the horizon haze to a dull ground color
+            return to_color(mix(HORIZON, GROUND, (-elevation * 8.0).min(1.0)));
+        }
+
+        let sky = mix(HORIZON, ZENITH, elevation.sqrt());
+
+        // Brighten the sky around the sun, with a small disk on top of it
+        let cos_gamma = NormalVector::dot_product(sun, direction);
+        let glow = 0.5 * ((cos_gamma - 1.0) * 8.0).exp();
+        let disk = if cos_gamma > 0.9995 { 1.0 } else { 0.0 };
+
+        to_color(mix(sky, SUN, (glow + disk).min(1.0)))
+    }
+}
@@ -1,17 +1,24 @@
-use crate::{common::*, geometry::Vector};
+use rand::Rng;
+
+use crate::{
+    common::*,
+    geometry::Vector,
+    scene::{Keyframe, Perspective, Projection, Ray},
+};
 
 pub struct Camera {
     pub pos: Point,
     center_of_view: Point,
     up: NormalVector,
-    x_fov_angle: f64,
-    y_fov_angle: f64,
-    pub z_min: f64,
     pub height: usize,
     pub width: usize,
-    top_left: Vector,
-    vunit_x: Vector,
-    vunit_y: Vector,
+    aperture: f64,
+    focus_distance: Option<f64>,
+    motion: Option<Keyframe>,
+    projection: Box<dyn Projection>,
+    forward: Vector,
+    right: Vector,
+    top: Vector,
 }
 
 impl Camera {
@@ -19,63 +26,161 @@ impl Camera {
         pos: Point,
         center_of_view: Point,
         up: NormalVector,
-        x_fov_angle: f64,
-        y_fov_angle: f64,
+        fov_angle: f64,
         z_min: f64,
-        height: usize,
         width: usize,
+        height: usize,
     ) -> Self {
-        let w = 2.0 * z_min * (y_fov_angle / 2.0).to_radians().tan();
-        let h = 2.0 * z_min * (x_fov_angle / 2.0).to_radians().tan();
-
-        let unit_y = w / (width as f64);
-        let unit_x = h / (height as f64);
-
-        let vec_center = Vector::from(pos, center_of_view).normalize().vector() * z_min;
-
-        let vunit_y = Vector::cross_product(&up.vector(), &vec_center.normalize().vector())
-            .normalize()
-            .vector()
-            * unit_y;
-        let vunit_x = up.vector() * unit_x;
-
-        let top_left = Vector::from(ORIGIN, pos)
-            + vec_center
-            + vunit_y * (width as f64 / 2.0)
-            + vunit_x * (height as f64 / 2.0);
+        let aspect_ratio = width as f64 / height as f64;
+        let (forward, right, top) = Camera::frame(pos, center_of_view, up);
 
         Camera {
             pos,
             center_of_view,
             up,
-            x_fov_angle,
-            y_fov_angle,
-            z_min,
             height,
             width,
-            top_left,
-            vunit_x,
-            vunit_y,
+            aperture: 0.0,
+            focus_distance: None,
+            motion: None,
+            projection: Box::new(Perspective::new(fov_angle, aspect_ratio, z_min)),
+            forward,
+            right,
+            top,
         }
     }
 
-    pub fn get_pixel_pos(&self, x: usize, y: usize) -> Point {
-        let pos = self.top_left - self.vunit_x * (x as f64) - self.vunit_y * (y as f64);
+    /// Orthonormal basis of the camera: forward, right and up
+    fn frame(pos: Point, center_of_view: Point, up: NormalVector) -> (Vector, Vector, Vector) {
+        let forward = Vector::from(pos, center_of_view).normalize().vector();
+        let right = Vector::cross_product(&forward, &up.vector())
+            .normalize()
+            .vector();
+        let top = Vector::cross_product(&right, &forward);
+
+        (forward, right, top)
+    }
 
-        Point(pos.x, pos.y, pos.z)
+    pub fn with_projection<P: Projection + 'static>(self, projection: P) -> Self {
+        Camera {
+            projection: Box::new(projection),
+            ..self
+        }
     }
 
-    pub fn move_to(&mut self, pos: Point) {
-        *self = Camera::new(
-            pos,
-            self.center_of_view,
-            self.up,
-            self.x_fov_angle,
-            self.y_fov_angle,
-            self.z_min,
-            self.height,
-            self.width,
+    /// Turn the camera into a thin lens of radius `aperture`, objects at
+    /// `focus_distance` are sharp. Without a focus distance, the camera
+    /// focuses on its center of view.
+    pub fn with_lens(self, aperture: f64, focus_distance: Option<f64>) -> Self {
+        Camera {
+            aperture,
+            focus_distance,
+            ..self
+        }
+    }
+
+    /// Current placement of the camera
+    pub fn key(&self) -> Keyframe {
+        Keyframe {
+            time: 0.0,
+            pos: self.pos,
+            look_at: self.center_of_view,
+            up: self.up,
+            fov_angle: self.projection.fov(),
+        }
+    }
+
+    /// Placement reached at the end of the frame, rays being cast in between
+    pub fn set_motion(&mut self, end: Option<Keyframe>) {
+        self.motion = end;
+    }
+
+    /// Position and basis of the camera at `time`
+    fn frame_at(&self, time: f64) -> (Vector, Vector, Vector, Vector) {
+        match &self.motion {
+            Some(end) if time > 0.0 => {
+                let lerp = |a: Point, b: Point| {
+                    (Vector::from(ORIGIN, a) + Vector::from(a, b) * time).to_point()
+                };
+                let pos = lerp(self.pos, end.pos);
+                let up =
+                    (self.up.vector() + (end.up.vector() - self.up.vector()) * time).normalize();
+                let (forward, right, top) =
+                    Camera::frame(pos, lerp(self.center_of_view, end.look_at), up);
+
+                (Vector::from(ORIGIN, pos), forward, right, top)
+            }
+            _ => (
+                Vector::from(ORIGIN, self.pos),
+                self.forward,
+                self.right,
+                self.top,
+            ),
+        }
+    }
+
+    /// Ray going through the pixel at column `x` and row `y` at `time`,
+    /// fractional parts being positions within the pixel. `None` if the
+    /// projection does not cover the pixel.
+    pub fn get_ray<R: Rng>(&self, x: f64, y: f64, time: f64, rng: &mut R) -> Option<Ray> {
+        let (sx, sy) = (
+            2.0 * x / self.width as f64 - 1.0,
+            1.0 - 2.0 * y / self.height as f64,
         );
+        let (origin, direction) = self.projection.ray(sx, sy)?;
+        let d = direction.vector();
+
+        // The lens only makes sense for rays going forward
+        let (origin, direction) = if self.aperture > 0.0 && d.z > f64::EPSILON {
+            let focus_distance = self
+                .focus_distance
+                .unwrap_or_else(|| Vector::from(self.pos, self.center_of_view).norm());
+
+            // Where the ray crosses the lens and the focus planes
+            let center = origin - d * (origin.z / d.z);
+            let focus = center + d * (focus_distance / d.z);
+
+            // Uniform sample over the lens disk
+            let r = self.aperture * rng.gen::<f64>().sqrt();
+            let theta = rng.gen::<f64>() * 2.0 * std::f64::consts::PI;
+            let lens = center + Vector::new(r * theta.cos(), r * theta.sin(), 0.0);
+
+            let d = (focus - lens).normalize().vector();
+            (lens + d * (origin.z / d.z), d)
+        } else {
+            (origin, d)
+        };
+
+        let (pos, forward, right, top) = self.frame_at(time);
+        let to_world = |v: Vector| right * v.x + top * v.y + forward * v.z;
+
+        Some(Ray {
+            energy: 1.0,
+            time,
+            origin: (pos + to_world(origin)).to_point(),
+            direction: to_world(direction).normalize(),
+        })
+    }
+
+    pub fn move_to(&mut self, pos: Point) {
+        let (forward, right, top) = Camera::frame(pos, self.center_of_view, self.up);
+
+        self.pos = pos;
+        self.forward = forward;
+        self.right = right;
+        self.top = top;
+    }
+
+    /// Place the camera as given by an animation key, a field of view
+    /// changing the one of the projection if it has one
+    pub fn look(&mut self, key: &Keyframe) {
+        self.center_of_view = key.look_at;
+        self.up = key.up;
+        self.move_to(key.pos);
+
+        if let Some(fov_angle) = key.fov_angle {
+            self.projection.set_fov(fov_angle);
+        }
     }
 
     pub fn rotate_around_center_of_view(&mut self, angle: f64) {
@@ -88,3 +193,124 @@ impl Camera {
         self.move_to(new_pos.to_point());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Orthographic;
+
+    fn camera() -> Camera {
+        Camera::new(
+            Point(0.0, 0.0, 0.0),
+            Point(0.0, 0.0, 10.0),
+            Vector::new(1.0, 0.0, 0.0).normalize(),
+            90.0,
+            1.0,
+            100,
+            100,
+        )
+    }
+
+    #[test]
+    fn pinhole_center() {
+        let ray = camera()
+            .get_ray(50.0, 50.0, 0.0, &mut rand::thread_rng())
+            .unwrap();
+
+        assert!((ray.direction.vector().z - 1.0).abs() < 1e-9);
+        assert!((ray.origin.2 - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn lens_focus() {
+        let cam = camera().with_lens(0.5, Some(5.0));
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..16 {
+            let ray = cam.get_ray(70.0, 20.0, 0.0, &mut rng).unwrap();
+            let pinhole = camera().get_ray(70.0, 20.0, 0.0, &mut rng).unwrap();
+
+            // Every ray of a pixel meets the pinhole ray on the focus plane
+            let t = (5.0 - ray.origin.2) / ray.direction.vector().z;
+            let p = Vector::from(ORIGIN, ray.origin) + ray.direction.vector() * t;
+            let t = (5.0 - pinhole.origin.2) / pinhole.direction.vector().z;
+            let q = Vector::from(ORIGIN, pinhole.origin) + pinhole.direction.vector() * t;
+
+            assert!((p - q).norm() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn orbit_keeps_projection() {
+        let mut cam = camera().with_projection(Orthographic::new(2.0, 2.0));
+        cam.rotate_around_center_of_view(std::f64::consts::PI);
+
+        let ray = cam
+            .get_ray(50.0, 50.0, 0.0, &mut rand::thread_rng())
+            .unwrap();
+        assert!((ray.origin.2 - 20.0).abs() < 1e-9);
+        assert!((ray.direction.vector().z + 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn look_keeps_projection() {
+        let mut cam = camera().with_projection(Orthographic::new(2.0, 2.0));
+        let mut key = cam.key();
+        key.fov_angle = Some(30.0);
+        cam.look(&key);
+
+        let ray = cam
+            .get_ray(100.0, 50.0, 0.0, &mut rand::thread_rng())
+            .unwrap();
+        assert!((ray.direction.vector().z - 1.0).abs() < 1e-9);
+
+        // A perspective one gets the new field of view
+        let mut cam = camera();
+        key.fov_angle = Some(60.0);
+        cam.look(&key);
+
+        let ray = cam
+            .get_ray(100.0, 50.0, 0.0, &mut rand::thread_rng())
+            .unwrap();
+        let d = ray.direction.vector();
+        assert!((d.y.atan2(d.z).to_degrees() - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn aspect_ratio() {
+        let cam = Camera::new(
+            Point(0.0, 0.0, 0.0),
+            Point(0.0, 0.0, 10.0),
+            Vector::new(1.0, 0.0, 0.0).normalize(),
+            90.0,
+            1.0,
+            200,
+            100,
+        );
+        let mut rng = rand::thread_rng();
+
+        // Pixels are square: the top edge is half as far as the right one
+        let right = cam.get_ray(200.0, 50.0, 0.0, &mut rng).unwrap();
+        let top = cam.get_ray(100.0, 0.0, 0.0, &mut rng).unwrap();
+
+        assert!((right.origin.1 - 1.0).abs() < 1e-9);
+        assert!((top.origin.0 - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn motion() {
+        let mut cam = camera();
+        let mut end = cam.key();
+        end.pos = Point(0.0, 4.0, 0.0);
+        end.look_at = Point(0.0, 4.0, 10.0);
+        cam.set_motion(Some(end));
+
+        let mut rng = rand::thread_rng();
+        let start = cam.get_ray(50.0, 50.0, 0.0, &mut rng).unwrap();
+        let middle = cam.get_ray(50.0, 50.0, 0.5, &mut rng).unwrap();
+
+        assert!(start.origin.1.abs() < 1e-9);
+        assert!((middle.origin.1 - 2.0).abs() < 1e-9);
+        assert!((middle.direction.vector().z - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{common::*, geometry::BoundingBox};
+
+use super::{Object, Ray, TextureMaterial};
+
+/// Truncated cone going from `a` with radius `ra` to `b` with radius `rb`,
+/// closed by a disk on each end
+pub struct Cone<T: TextureMaterial> {
+    a: Point,
+    b: Point,
+    ra: f64,
+    rb: f64,
+    texture: T,
+    direction: NormalVector,
+    length: f64,
+    // Radius variation per unit of length along the axis
+    slope: f64,
+    ref_normal: NormalVector,
+}
+
+impl<T> Cone<T>
+where
+    T: TextureMaterial,
+{
+    pub fn new(a: Point, b: Point, ra: f64, rb: f64, texture: T) -> Cone<T> {
+        let axis = Vector::from(a, b);
+        let length = axis.norm();
+        let direction = axis.normalize();
+
+        let n = direction.vector();
+        let helper = if n.x.abs() > 0.9 {
+            Vector::new(0.0, 1.0, 0.0)
+        } else {
+            Vector::new(1.0, 0.0, 0.0)
+        };
+        let ref_normal = Vector::cross_product(&n, &helper).normalize();
+
+        Cone {
+            a,
+            b,
+            ra,
+            rb,
+            texture,
+            direction,
+            length,
+            slope: (rb - ra) / length,
+            ref_normal,
+        }
+    }
+
+    fn height(&self, p: Point) -> f64 {
+        Vector::dot_product(&Vector::from(self.a, p), &self.direction.vector())
+    }
+
+    fn radial(&self, p: Point) -> Vector {
+        let n = self.direction.vector();
+        Vector::from(self.a, p) - n * self.height(p)
+    }
+}
+
+impl<T> Object for Cone<T>
+where
+    T: TextureMaterial,
+{
+    fn intersects(&self, ray: Ray) -> Option<f64> {
+        let w = self.direction.vector();
+        let v = ray.direction.vector();
+        let d = Vector::from(self.a, ray.origin);
+
+        let dot_vw = Vector::dot_product(&v, &w);
+        let dot_dw = Vector::dot_product(&d, &w);
+
+        // Components orthogonal to the axis
+        let vp = v - w * dot_vw;
+        let dp = d - w * dot_dw;
+
+        // Radius along the ray: r(t) = c0 + t * c1
+        let c0 = self.ra + self.slope * dot_dw;
+        let c1 = self.slope * dot_vw;
+
+        let a = Vector::dot_product(&vp, &vp) - c1 * c1;
+        let b = 2.0 * (Vector::dot_product(&vp, &dp) - c0 * c1);
+        let c = Vector::dot_product(&dp, &dp) - c0 * c0;
+
+        let mut intersections = Vec::new();
+
+        if a.abs() > f64::EPSILON {
+            let delta = b * b - 4.0 * a * c;
+            if delta >= 0.0 {
+                for &t in &[
+                    (-b + delta.sqrt()) / (2.0 * a),
+                    (-b - delta.sqrt()) / (2.0 * a),
+                ] {
+                    let h = dot_dw + t * dot_vw;
+                    if t > 0.0 && h >= 0.0 && h <= self.length && c0 + t * c1 >= 0.0 {
+                        intersections.push(t);
+                    }
+                }
+            }
+        }
+
+        // End caps
+        if dot_vw.abs() > f64::EPSILON {
+            let origin = Vector::from(ORIGIN, ray.origin);
+            let mut mark = |p: Point, r: f64| {
+                let t = Vector::dot_product(&Vector::from(ray.origin, p), &w) / dot_vw;
+
+                if t > 0.0 {
+                    let intersection = (origin + t * v).to_point();
+                    let v = Vector::from(p, intersection);
+                    if Vector::dot_product(&v, &v) <= r * r {
+                        intersections.push(t);
+                    }
+                }
+            };
+
+            mark(self.a, self.ra);
+            mark(self.b, self.rb);
+        }
+
+        intersections
+            .into_iter()
+            .fold(None, |acc, x| Some(acc.map_or(x, |v: f64| v.min(x))))
+    }
+
+    fn normal(&self, p: Point) -> NormalVector {
+        let epsilon = 1e-6;
+        let h = self.height(p);
+        let radial = self.radial(p);
+
+        let r = self.ra + self.slope * h;
+        if radial.norm() < r - epsilon {
+            // Inside the side surface, so on one of the caps
+            return if h < self.length / 2.0 {
+                -self.direction
+            } else {
+                self.direction
+            };
+        }
+
+        (radial.normalize().vector() - self.direction.vector() * self.slope).normalize()
+    }
+
+    fn diffusion(&self, p: Point) -> (f64, f64, f64) {
+        let (u, v) = self.map_to_texture(p);
+        self.texture.diffusion(u, v)
+    }
+
+    fn specularity(&self, _p: Point) -> f64 {
+        self.texture.specularity(0, 0)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        Some(BoundingBox::from_points(&[self.a, self.b]).expand(self.ra.max(self.rb)))
+    }
+
+    fn map_to_texture(&self, p: Point) -> (f64, f64) {
+        let radial = self.radial(p).normalize();
+        let v = self.height(p) / self.length;
+        let u = NormalVector::dot_product(&self.ref_normal, &radial)
+            .clamp(-1.0, 1.0)
+            .acos()
+            / std::f64::consts::PI;
+        (u, v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{ORIGIN, WHITE};
+    use crate::scene::texture::UniformTexture;
+
+    use super::*;
+
+    fn cone() -> Cone<UniformTexture> {
+        Cone::new(
+            Point(0.0, -1.0, 5.0),
+            Point(0.0, 1.0, 5.0),
+            1.0,
+            0.5,
+            UniformTexture::new(WHITE, 0.0, 0.0),
+        )
+    }
+
+    #[test]
+    fn intersects_side() {
+        let ray = Ray {
+            energy: 1.0,
+            time: 0.0,
+            origin: ORIGIN,
+            direction: Vector::new(0.0, 0.0, 1.0).normalize(),
+        };
+
+        // The radius at mid-height is 0.75
+        let d = cone().intersects(ray).unwrap();
+        assert!((d - 4.25).abs() < 1e-9)
+    }
+
+    #[test]
+    fn intersects_cap() {
+        let ray = Ray {
+            energy: 1.0,
+            time: 0.0,
+            origin: Point(0.0, 5.0, 5.0),
+            direction: Vector::new(0.0, -1.0, 0.0).normalize(),
+        };
+
+        let d = cone().intersects(ray).unwrap();
+        assert!((d - 4.0).abs() < 1e-9)
+    }
+
+    #[test]
+    fn intersects_false_narrow_end() {
+        // Passes within the wide radius but outside the narrow one
+        let ray = Ray {
+            energy: 1.0,
+            time: 0.0,
+            origin: Point(0.6, 0.9, 0.0),
+            direction: Vector::new(0.0, 0.0, 1.0).normalize(),
+        };
+
+        assert!(cone().intersects(ray).is_none())
+    }
+}
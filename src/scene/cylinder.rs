@@ -1,4 +1,4 @@
-use crate::common::*;
+use crate::{common::*, geometry::BoundingBox};
 use std::cell::UnsafeCell;
 
 use super::{Object, Ray, TextureMaterial};
@@ -113,6 +113,10 @@ where
         self.texture.specularity(0, 0)
     }
 
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        Some(BoundingBox::from_points(&[self.a, self.b]).expand(self.r))
+    }
+
     fn diffusion(&self, p: Point) -> (f64, f64, f64) {
         let (u, v) = self.map_to_texture(p);
         self.texture.diffusion(u, v)
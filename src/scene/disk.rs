@@ -0,0 +1,104 @@
+use crate::{common::*, geometry::BoundingBox};
+
+use super::{Object, Plane, Ray, TextureMaterial};
+
+pub struct Disk<T: TextureMaterial> {
+    plane: Plane<T>,
+    radius: f64,
+}
+
+impl<T> Disk<T>
+where
+    T: TextureMaterial,
+{
+    pub fn new(center: Point, normal: NormalVector, radius: f64, texture: T) -> Disk<T> {
+        Disk {
+            plane: Plane::new(center, normal, texture),
+            radius,
+        }
+    }
+}
+
+impl<T> Object for Disk<T>
+where
+    T: TextureMaterial,
+{
+    fn intersects(&self, ray: Ray) -> Option<f64> {
+        let t = self.plane.intersects(ray)?;
+
+        let p = (Vector::from(ORIGIN, ray.origin) + t * ray.direction.vector()).to_point();
+        let v = Vector::from(self.plane.point(), p);
+
+        if Vector::dot_product(&v, &v) <= self.radius * self.radius {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    fn normal(&self, p: Point) -> NormalVector {
+        self.plane.normal(p)
+    }
+
+    fn diffusion(&self, p: Point) -> (f64, f64, f64) {
+        self.plane.diffusion(p)
+    }
+
+    fn specularity(&self, p: Point) -> f64 {
+        self.plane.specularity(p)
+    }
+
+    fn map_to_texture(&self, p: Point) -> (f64, f64) {
+        self.plane.map_to_texture(p)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        Some(BoundingBox::from_points(&[self.plane.point()]).expand(self.radius))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{ORIGIN, WHITE};
+    use crate::scene::texture::UniformTexture;
+
+    use super::*;
+
+    #[test]
+    fn intersects_true() {
+        let d = Disk::new(
+            Point(0.0, 0.0, 5.0),
+            Vector::new(0.0, 0.0, -1.0).normalize(),
+            1.0,
+            UniformTexture::new(WHITE, 0.0, 0.0),
+        );
+
+        let ray = Ray {
+            energy: 1.0,
+            time: 0.0,
+            origin: ORIGIN,
+            direction: Vector::new(0.0, 0.1, 1.0).normalize(),
+        };
+
+        assert!(d.intersects(ray).is_some())
+    }
+
+    #[test]
+    fn intersects_false_outside() {
+        let d = Disk::new(
+            Point(0.0, 0.0, 5.0),
+            Vector::new(0.0, 0.0, -1.0).normalize(),
+            1.0,
+            UniformTexture::new(WHITE, 0.0, 0.0),
+        );
+
+        let ray = Ray {
+            energy: 1.0,
+            time: 0.0,
+            origin: ORIGIN,
+            direction: Vector::new(0.0, 1.0, 1.0).normalize(),
+        };
+
+        assert!(d.intersects(ray).is_none())
+    }
+}
@@ -0,0 +1,148 @@
+use std::rc::Rc;
+
+use crate::{common::*, geometry::BoundingBox};
+
+use super::{Object, ObjectContainer, Ray};
+
+/// Objects gathered into a single one, which can be shared between several
+/// `Instance`s
+pub struct Group {
+    objects: Rc<ObjectContainer>,
+    bbox: Option<BoundingBox>,
+}
+
+impl Group {
+    pub fn new(objects: Rc<ObjectContainer>) -> Group {
+        let bbox = objects
+            .iter()
+            .map(|obj| obj.bounding_box())
+            .try_fold(BoundingBox::empty(), |acc, b| b.map(|b| acc.union(b)));
+
+        Group { objects, bbox }
+    }
+
+    fn closest(&self, ray: Ray) -> Option<(f64, &dyn Object)> {
+        if let Some(bbox) = self.bbox {
+            bbox.intersects(ray.origin, &ray.direction)?;
+        }
+
+        self.objects
+            .iter()
+            .filter_map(|obj| obj.intersects(ray).map(|d| (d, obj.as_ref())))
+            .fold(
+                None,
+                |acc: Option<(f64, &dyn Object)>, (d, obj)| match acc {
+                    Some((best, _)) if best <= d => acc,
+                    _ => Some((d, obj)),
+                },
+            )
+    }
+
+    /// Object whose surface goes through p, found by probing the point along
+    /// each axis
+    fn object_at(&self, p: Point) -> Option<&dyn Object> {
+        let epsilon = 1e-4;
+        let axes = [
+            Vector::new(1.0, 0.0, 0.0),
+            Vector::new(-1.0, 0.0, 0.0),
+            Vector::new(0.0, 1.0, 0.0),
+            Vector::new(0.0, -1.0, 0.0),
+            Vector::new(0.0, 0.0, 1.0),
+            Vector::new(0.0, 0.0, -1.0),
+        ];
+
+        let score = |obj: &dyn Object| {
+            axes.iter()
+                .filter_map(|&axis| {
+                    let ray = Ray {
+                        energy: 1.0,
+                        time: 0.0,
+                        origin: (Vector::from(ORIGIN, p) - axis * epsilon).to_point(),
+                        direction: axis.normalize(),
+                    };
+                    obj.intersects(ray).map(|d| (d - epsilon).abs())
+                })
+                .fold(f64::INFINITY, f64::min)
+        };
+
+        self.objects
+            .iter()
+            .map(|obj| (score(obj.as_ref()), obj.as_ref()))
+            .filter(|(s, _)| s.is_finite())
+            .fold(
+                None,
+                |acc: Option<(f64, &dyn Object)>, (s, obj)| match acc {
+                    Some((best, _)) if best <= s => acc,
+                    _ => Some((s, obj)),
+                },
+            )
+            .map(|(_, obj)| obj)
+    }
+}
+
+/// Object borrowed from an aggregate, returned as its surface
+pub(super) struct Borrowed<'a>(pub &'a dyn Object);
+
+impl<'a> Object for Borrowed<'a> {
+    fn intersects(&self, ray: Ray) -> Option<f64> {
+        self.0.intersects(ray)
+    }
+
+    fn normal(&self, p: Point) -> NormalVector {
+        self.0.normal(p)
+    }
+
+    fn diffusion(&self, p: Point) -> (f64, f64, f64) {
+        self.0.diffusion(p)
+    }
+
+    fn specularity(&self, p: Point) -> f64 {
+        self.0.specularity(p)
+    }
+
+    fn map_to_texture(&self, p: Point) -> (f64, f64) {
+        self.0.map_to_texture(p)
+    }
+
+    fn surface(&self, ray: Ray) -> Option<Box<dyn Object + '_>> {
+        self.0.surface(ray)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        self.0.bounding_box()
+    }
+}
+
+impl Object for Group {
+    fn intersects(&self, ray: Ray) -> Option<f64> {
+        self.closest(ray).map(|(d, _)| d)
+    }
+
+    fn surface(&self, ray: Ray) -> Option<Box<dyn Object + '_>> {
+        self.closest(ray)
+            .map(|(_, obj)| obj.surface(ray).unwrap_or_else(|| Box::new(Borrowed(obj))))
+    }
+
+    fn normal(&self, p: Point) -> NormalVector {
+        self.object_at(p)
+            .map_or(Vector::new(1.0, 0.0, 0.0).normalize(), |obj| obj.normal(p))
+    }
+
+    fn diffusion(&self, p: Point) -> (f64, f64, f64) {
+        self.object_at(p)
+            .map_or((0.0, 0.0, 0.0), |obj| obj.diffusion(p))
+    }
+
+    fn specularity(&self, p: Point) -> f64 {
+        self.object_at(p).map_or(0.0, |obj| obj.specularity(p))
+    }
+
+    fn map_to_texture(&self, p: Point) -> (f64, f64) {
+        self.object_at(p)
+            .map_or((0.0, 0.0), |obj| obj.map_to_texture(p))
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        self.bbox
+    }
+}
@@ -0,0 +1,482 @@
+use std::rc::Rc;
+
+use crate::{common::*, geometry::BoundingBox};
+
+use super::{Object, Ray, TextureMaterial};
+
+/// Vertex attributes, shared between all the meshes built from the same file
+#[derive(Default)]
+pub struct MeshBuffers {
+    pub vertices: Vec<Point>,
+    pub normals: Vec<Vector>,
+    pub uvs: Vec<(f64, f64)>,
+}
+
+/// Indices of a triangle in the mesh buffers
+#[derive(Copy, Clone, Debug)]
+pub struct Face {
+    pub vertices: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+}
+
+const BVH_LEAF_SIZE: usize = 4;
+
+/// Whether a face at `distance` is closer than the best one found so far
+fn closer(best: Option<(f64, usize)>, distance: f64) -> bool {
+    match best {
+        Some((d, _)) => distance < d,
+        None => true,
+    }
+}
+
+enum BvhNode {
+    Leaf {
+        bbox: BoundingBox,
+        start: usize,
+        end: usize,
+    },
+    Node {
+        bbox: BoundingBox,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl BvhNode {
+    fn bbox(&self) -> &BoundingBox {
+        match self {
+            BvhNode::Leaf { bbox, .. } | BvhNode::Node { bbox, .. } => bbox,
+        }
+    }
+}
+
+pub struct Mesh<T: TextureMaterial> {
+    buffers: Rc<MeshBuffers>,
+    faces: Vec<Face>,
+    texture: T,
+    bvh: Vec<BvhNode>,
+}
+
+impl<T> Mesh<T>
+where
+    T: TextureMaterial,
+{
+    pub fn new(buffers: Rc<MeshBuffers>, faces: Vec<Face>, texture: T) -> Mesh<T> {
+        let mut mesh = Mesh {
+            buffers,
+            faces,
+            texture,
+            bvh: Vec::new(),
+        };
+
+        if !mesh.faces.is_empty() {
+            let len = mesh.faces.len();
+            mesh.build_bvh(0, len);
+        }
+
+        mesh
+    }
+
+    fn points(&self, face: &Face) -> (Point, Point, Point) {
+        let v = &self.buffers.vertices;
+        (
+            v[face.vertices[0]],
+            v[face.vertices[1]],
+            v[face.vertices[2]],
+        )
+    }
+
+    fn face_bbox(&self, face: &Face) -> BoundingBox {
+        let (a, b, c) = self.points(face);
+        BoundingBox::from_points(&[a, b, c])
+    }
+
+    fn centroid(&self, face: &Face, axis: usize) -> f64 {
+        let (a, b, c) = self.points(face);
+        match axis {
+            0 => a.0 + b.0 + c.0,
+            1 => a.1 + b.1 + c.1,
+            _ => a.2 + b.2 + c.2,
+        }
+    }
+
+    /// Build the node for faces[start..end], returning its index
+    fn build_bvh(&mut self, start: usize, end: usize) -> usize {
+        let bbox = self.faces[start..end]
+            .iter()
+            .fold(BoundingBox::empty(), |acc, f| acc.union(self.face_bbox(f)));
+
+        let index = self.bvh.len();
+
+        if end - start <= BVH_LEAF_SIZE {
+            self.bvh.push(BvhNode::Leaf { bbox, start, end });
+            return index;
+        }
+
+        // Split at the median along the widest axis of the centroids
+        let centroids = self.faces[start..end]
+            .iter()
+            .fold(BoundingBox::empty(), |acc, f| {
+                let (a, b, c) = self.points(f);
+                acc.extend(Point(
+                    (a.0 + b.0 + c.0) / 3.0,
+                    (a.1 + b.1 + c.1) / 3.0,
+                    (a.2 + b.2 + c.2) / 3.0,
+                ))
+            });
+        let axis = centroids.longest_axis();
+
+        let mut faces = self.faces[start..end].to_vec();
+        faces.sort_by(|a, b| {
+            self.centroid(a, axis)
+                .partial_cmp(&self.centroid(b, axis))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        self.faces[start..end].copy_from_slice(&faces);
+
+        // Reserve our slot before building the children
+        self.bvh.push(BvhNode::Leaf { bbox, start, end });
+
+        let mid = (start + end) / 2;
+        let left = self.build_bvh(start, mid);
+        let right = self.build_bvh(mid, end);
+        self.bvh[index] = BvhNode::Node { bbox, left, right };
+
+        index
+    }
+
+    fn intersects_face(&self, face: &Face, ray: &Ray) -> Option<f64> {
+        // Moeller-Trumbore algorithm
+
+        let (a, b, c) = self.points(face);
+        let ab = Vector::from(a, b);
+        let ac = Vector::from(a, c);
+
+        let h = Vector::cross_product(&ray.direction.vector(), &ac);
+        let dot = Vector::dot_product(&ab, &h);
+
+        if dot.abs() < f64::EPSILON {
+            return None;
+        }
+
+        let inv_dot = 1.0 / dot;
+        let ao = Vector::from(a, ray.origin);
+        let u = inv_dot * Vector::dot_product(&ao, &h);
+
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let cross = Vector::cross_product(&ao, &ab);
+        let v = inv_dot * Vector::dot_product(&ray.direction.vector(), &cross);
+
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = inv_dot * Vector::dot_product(&ac, &cross);
+        if t > 0.0 {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    /// Closest face hit by the ray along with the distance to it
+    fn closest(&self, ray: &Ray) -> Option<(f64, usize)> {
+        let mut best: Option<(f64, usize)> = None;
+        let mut stack = vec![0];
+
+        if self.bvh.is_empty() {
+            return None;
+        }
+
+        while let Some(index) = stack.pop() {
+            let node = &self.bvh[index];
+            match node.bbox().intersects(ray.origin, &ray.direction) {
+                Some((near, _)) if closer(best, near) => (),
+                _ => continue,
+            }
+
+            match node {
+                BvhNode::Leaf { start, end, .. } => {
+                    for i in *start..*end {
+                        if let Some(t) = self.intersects_face(&self.faces[i], ray) {
+                            if closer(best, t) {
+                                best = Some((t, i));
+                            }
+                        }
+                    }
+                }
+                BvhNode::Node { left, right, .. } => {
+                    stack.push(*left);
+                    stack.push(*right);
+                }
+            }
+        }
+
+        best
+    }
+
+    /// Barycentric coordinates of p relative to the face
+    fn barycentric(&self, face: &Face, p: Point) -> (f64, f64, f64) {
+        let (a, b, c) = self.points(face);
+        let ab = Vector::from(a, b);
+        let ac = Vector::from(a, c);
+        let ap = Vector::from(a, p);
+
+        let d00 = Vector::dot_product(&ab, &ab);
+        let d01 = Vector::dot_product(&ab, &ac);
+        let d11 = Vector::dot_product(&ac, &ac);
+        let d20 = Vector::dot_product(&ap, &ab);
+        let d21 = Vector::dot_product(&ap, &ac);
+
+        let denom = d00 * d11 - d01 * d01;
+        if denom.abs() < f64::EPSILON {
+            return (1.0, 0.0, 0.0);
+        }
+
+        let v = (d11 * d20 - d01 * d21) / denom;
+        let w = (d00 * d21 - d01 * d20) / denom;
+        (1.0 - v - w, v, w)
+    }
+
+    fn face_normal(&self, face: &Face, p: Point) -> NormalVector {
+        match face.normals {
+            Some(n) => {
+                let (a, b, c) = self.barycentric(face, p);
+                let normals = &self.buffers.normals;
+                (normals[n[0]] * a + normals[n[1]] * b + normals[n[2]] * c).normalize()
+            }
+            None => {
+                let (a, b, c) = self.points(face);
+                Vector::cross_product(&Vector::from(a, b), &Vector::from(a, c)).normalize()
+            }
+        }
+    }
+
+    fn face_uv(&self, face: &Face, p: Point) -> (f64, f64) {
+        match face.uvs {
+            Some(t) => {
+                let (a, b, c) = self.barycentric(face, p);
+                let uvs = &self.buffers.uvs;
+                (
+                    uvs[t[0]].0 * a + uvs[t[1]].0 * b + uvs[t[2]].0 * c,
+                    uvs[t[0]].1 * a + uvs[t[1]].1 * b + uvs[t[2]].1 * c,
+                )
+            }
+            None => (0.0, 0.0),
+        }
+    }
+
+    /// Face closest to p, used when shading without knowing the ray
+    fn locate(&self, p: Point) -> Option<usize> {
+        let epsilon = 1e-6;
+        let mut best: Option<(f64, usize)> = None;
+        let mut stack = vec![0];
+
+        if self.bvh.is_empty() {
+            return None;
+        }
+
+        while let Some(index) = stack.pop() {
+            let node = &self.bvh[index];
+            if !node.bbox().expand(epsilon).contains(p) {
+                continue;
+            }
+
+            match node {
+                BvhNode::Leaf { start, end, .. } => {
+                    for i in *start..*end {
+                        let face = &self.faces[i];
+                        if !self.face_bbox(face).expand(epsilon).contains(p) {
+                            continue;
+                        }
+
+                        let (a, b, c) = self.points(face);
+                        let n = Vector::cross_product(&Vector::from(a, b), &Vector::from(a, c))
+                            .normalize();
+                        let distance = Vector::dot_product(&Vector::from(a, p), &n.vector()).abs();
+                        if closer(best, distance) {
+                            best = Some((distance, i));
+                        }
+                    }
+                }
+                BvhNode::Node { left, right, .. } => {
+                    stack.push(*left);
+                    stack.push(*right);
+                }
+            }
+        }
+
+        best.map(|(_, i)| i)
+    }
+}
+
+/// A single face of a mesh, as seen by the shading code
+struct MeshFace<'a, T: TextureMaterial> {
+    mesh: &'a Mesh<T>,
+    face: Face,
+}
+
+impl<'a, T> Object for MeshFace<'a, T>
+where
+    T: TextureMaterial,
+{
+    fn intersects(&self, ray: Ray) -> Option<f64> {
+        self.mesh.intersects_face(&self.face, &ray)
+    }
+
+    fn normal(&self, p: Point) -> NormalVector {
+        self.mesh.face_normal(&self.face, p)
+    }
+
+    fn diffusion(&self, p: Point) -> (f64, f64, f64) {
+        let (u, v) = self.map_to_texture(p);
+        self.mesh.texture.diffusion(u, v)
+    }
+
+    fn specularity(&self, _p: Point) -> f64 {
+        self.mesh.texture.specularity(0, 0)
+    }
+
+    fn map_to_texture(&self, p: Point) -> (f64, f64) {
+        self.mesh.face_uv(&self.face, p)
+    }
+}
+
+impl<T> Object for Mesh<T>
+where
+    T: TextureMaterial,
+{
+    fn intersects(&self, ray: Ray) -> Option<f64> {
+        self.closest(&ray).map(|(t, _)| t)
+    }
+
+    fn surface(&self, ray: Ray) -> Option<Box<dyn Object + '_>> {
+        self.closest(&ray).map(|(_, i)| {
+            Box::new(MeshFace {
+                mesh: self,
+                face: self.faces[i],
+            }) as Box<dyn Object>
+        })
+    }
+
+    fn normal(&self, p: Point) -> NormalVector {
+        let i = self.locate(p).unwrap_or(0);
+        self.face_normal(&self.faces[i], p)
+    }
+
+    fn diffusion(&self, p: Point) -> (f64, f64, f64) {
+        let (u, v) = self.map_to_texture(p);
+        self.texture.diffusion(u, v)
+    }
+
+    fn specularity(&self, _p: Point) -> f64 {
+        self.texture.specularity(0, 0)
+    }
+
+    fn map_to_texture(&self, p: Point) -> (f64, f64) {
+        let i = self.locate(p).unwrap_or(0);
+        self.face_uv(&self.faces[i], p)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        self.bvh.first().map(|node| *node.bbox())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{ORIGIN, WHITE};
+    use crate::scene::texture::UniformTexture;
+
+    use super::*;
+
+    fn grid(n: usize) -> Mesh<UniformTexture> {
+        // n x n quads in the z = 5 plane, from (0, 0) to (n, n)
+        let mut buffers = MeshBuffers::default();
+        for i in 0..=n {
+            for j in 0..=n {
+                buffers.vertices.push(Point(i as f64, j as f64, 5.0));
+                buffers.normals.push(Vector::new(0.0, 0.0, -1.0));
+            }
+        }
+
+        let index = |i: usize, j: usize| i * (n + 1) + j;
+        let mut faces = Vec::new();
+        for i in 0..n {
+            for j in 0..n {
+                let quad = [
+                    index(i, j),
+                    index(i + 1, j),
+                    index(i + 1, j + 1),
+                    index(i, j + 1),
+                ];
+                faces.push(Face {
+                    vertices: [quad[0], quad[1], quad[2]],
+                    normals: Some([quad[0], quad[1], quad[2]]),
+                    uvs: None,
+                });
+                faces.push(Face {
+                    vertices: [quad[0], quad[2], quad[3]],
+                    normals: Some([quad[0], quad[2], quad[3]]),
+                    uvs: None,
+                });
+            }
+        }
+
+        Mesh::new(
+            Rc::new(buffers),
+            faces,
+            UniformTexture::new(WHITE, 0.0, 0.0),
+        )
+    }
+
+    #[test]
+    fn intersects_true() {
+        let mesh = grid(8);
+
+        let ray = Ray {
+            energy: 1.0,
+            time: 0.0,
+            origin: Point(6.3, 2.7, 0.0),
+            direction: Vector::new(0.0, 0.0, 1.0).normalize(),
+        };
+
+        let d = mesh.intersects(ray).unwrap();
+        assert!((d - 5.0).abs() < 1e-9)
+    }
+
+    #[test]
+    fn intersects_false() {
+        let mesh = grid(8);
+
+        let ray = Ray {
+            energy: 1.0,
+            time: 0.0,
+            origin: ORIGIN,
+            direction: Vector::new(-1.0, 0.0, 1.0).normalize(),
+        };
+
+        assert!(mesh.intersects(ray).is_none())
+    }
+
+    #[test]
+    fn interpolated_normal() {
+        let mesh = grid(2);
+        let n = mesh.normal(Point(0.5, 1.5, 5.0));
+        assert!((n.vector().z + 1.0).abs() < 1e-9)
+    }
+
+    #[test]
+    fn locate() {
+        let mesh = grid(8);
+
+        let p = Point(5.25, 2.5, 5.0);
+        let face = &mesh.faces[mesh.locate(p).unwrap()];
+        assert!(mesh.face_bbox(face).contains(p));
+        assert!(mesh.locate(Point(5.25, 2.5, 6.0)).is_none());
+    }
+}
@@ -1,19 +1,41 @@
+mod background;
 mod camera;
+mod cone;
 mod cylinder;
+mod disk;
+mod group;
 pub mod light;
+mod mesh;
+pub mod obj;
+mod plane;
+mod projection;
 mod ray;
 mod scene;
 mod sphere;
 pub mod texture;
+mod track;
+mod transform;
 mod triangle;
 
-use crate::{common::Point, geometry::NormalVector};
+use crate::{
+    common::Point,
+    geometry::{BoundingBox, NormalVector},
+};
 
+pub use background::Background;
 pub use camera::Camera;
+pub use projection::{Equirectangular, Fisheye, Orthographic, Perspective, Projection};
 pub use ray::Ray;
+pub use track::{CameraTrack, Interpolation, Keyframe};
 
+pub use cone::Cone;
 pub use cylinder::Cylinder;
+pub use disk::Disk;
+pub use group::Group;
+pub use mesh::{Face, Mesh, MeshBuffers};
+pub use plane::Plane;
 pub use sphere::Sphere;
+pub use transform::{Instance, Transformed};
 pub use triangle::Triangle;
 
 pub use scene::LightContainer;
@@ -33,6 +55,17 @@ pub trait Object {
     fn diffusion(&self, p: Point) -> (f64, f64, f64);
     fn specularity(&self, p: Point) -> f64;
     fn map_to_texture(&self, p: Point) -> (f64, f64);
+
+    /// Primitive hit by the ray, for objects made of several primitives which
+    /// need to know which one was hit to be shaded
+    fn surface(&self, _ray: Ray) -> Option<Box<dyn Object + '_>> {
+        None
+    }
+
+    /// Box containing the whole object, None if it is unbounded
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        None
+    }
 }
 
 pub trait Light {
@@ -62,7 +95,7 @@ macro_rules! sphere {
         use crate::scene::texture::UVMapTexture;
         use crate::scene::Sphere;
 
-        Sphere::<UVMapTexture>::new(Point($x, $y, $z), $r, UVMapTexture::new($c, $d, $s))
+        Sphere::<UVMapTexture>::new(Point($x, $y, $z), $r, UVMapTexture::new($c, $d, $s)?)
     }};
 }
 
@@ -92,6 +125,71 @@ macro_rules! cylinder {
         use crate::scene::texture::UVMapTexture;
         use crate::scene::Cylinder;
 
-        Cylinder::<UVMapTexture>::new($a, $b, $r, UVMapTexture::new($c, $d, $s))
+        Cylinder::<UVMapTexture>::new($a, $b, $r, UVMapTexture::new($c, $d, $s)?)
+    }};
+}
+
+#[macro_export]
+macro_rules! cone {
+    ($a:expr, $b:expr; $ra:expr, $rb:expr; <uniform>($c:expr, $d:expr, $s:expr)) => {{
+        use crate::scene::texture::UniformTexture;
+        use crate::scene::Cone;
+
+        Cone::<UniformTexture>::new($a, $b, $ra, $rb, UniformTexture::new($c, $d, $s))
+    }};
+
+    ($a:expr, $b:expr; $ra:expr, $rb:expr; <uvmapped>($c:expr, $d:expr, $s:expr)) => {{
+        use crate::scene::texture::UVMapTexture;
+        use crate::scene::Cone;
+
+        Cone::<UVMapTexture>::new($a, $b, $ra, $rb, UVMapTexture::new($c, $d, $s)?)
+    }};
+}
+
+#[macro_export]
+macro_rules! plane {
+    ($p:expr, $n:expr; <uniform>($c:expr, $d:expr, $s:expr)) => {{
+        use crate::scene::texture::UniformTexture;
+        use crate::scene::Plane;
+
+        Plane::<UniformTexture>::new($p, $n, UniformTexture::new($c, $d, $s))
+    }};
+
+    ($p:expr, $n:expr; <checker>($c1:expr, $c2:expr, $size:expr, $d:expr, $s:expr)) => {{
+        use crate::scene::texture::CheckerTexture;
+        use crate::scene::Plane;
+
+        Plane::<CheckerTexture>::new($p, $n, CheckerTexture::new($c1, $c2, $size, $d, $s))
+    }};
+
+    ($p:expr, $n:expr; <grid>($c1:expr, $c2:expr, $size:expr, $t:expr, $d:expr, $s:expr)) => {{
+        use crate::scene::texture::GridTexture;
+        use crate::scene::Plane;
+
+        Plane::<GridTexture>::new($p, $n, GridTexture::new($c1, $c2, $size, $t, $d, $s))
+    }};
+}
+
+#[macro_export]
+macro_rules! disk {
+    ($p:expr, $n:expr; $r:expr; <uniform>($c:expr, $d:expr, $s:expr)) => {{
+        use crate::scene::texture::UniformTexture;
+        use crate::scene::Disk;
+
+        Disk::<UniformTexture>::new($p, $n, $r, UniformTexture::new($c, $d, $s))
+    }};
+
+    ($p:expr, $n:expr; $r:expr; <checker>($c1:expr, $c2:expr, $size:expr, $d:expr, $s:expr)) => {{
+        use crate::scene::texture::CheckerTexture;
+        use crate::scene::Disk;
+
+        Disk::<CheckerTexture>::new($p, $n, $r, CheckerTexture::new($c1, $c2, $size, $d, $s))
+    }};
+
+    ($p:expr, $n:expr; $r:expr; <grid>($c1:expr, $c2:expr, $size:expr, $t:expr, $d:expr, $s:expr)) => {{
+        use crate::scene::texture::GridTexture;
+        use crate::scene::Disk;
+
+        Disk::<GridTexture>::new($p, $n, $r, GridTexture::new($c1, $c2, $size, $t, $d, $s))
     }};
 }
@@ -0,0 +1,347 @@
+//! Wavefront OBJ/MTL loader
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
+
+use crate::common::*;
+
+use super::{
+    texture::{UVMapTexture, UniformTexture},
+    Face, Mesh, MeshBuffers, ObjectContainer,
+};
+
+#[derive(Clone, Debug)]
+struct Material {
+    diffuse: Color,
+    specular: f64,
+    texture: Option<String>,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material {
+            diffuse: Color(200, 200, 200),
+            specular: 0.3,
+            texture: None,
+        }
+    }
+}
+
+fn invalid(line: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("Invalid line: {}", line),
+    )
+}
+
+fn parse_floats(words: std::str::SplitWhitespace, line: &str) -> std::io::Result<Vec<f64>> {
+    words
+        .map(|w| w.parse::<f64>().map_err(|_| invalid(line)))
+        .collect()
+}
+
+/// Resolve a 1-based, possibly negative, OBJ index
+fn parse_index(word: &str, len: usize, line: &str) -> std::io::Result<usize> {
+    let i = word.parse::<i64>().map_err(|_| invalid(line))?;
+    let index = if i < 0 { len as i64 + i } else { i - 1 };
+
+    if index < 0 || index >= len as i64 {
+        Err(invalid(line))
+    } else {
+        Ok(index as usize)
+    }
+}
+
+fn parse_mtl(path: &Path) -> std::io::Result<HashMap<String, Material>> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let file = File::open(path)?;
+
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, Material)> = None;
+
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            Some("newmtl") => {
+                if let Some((name, mat)) = current.take() {
+                    materials.insert(name, mat);
+                }
+                let name = words.next().ok_or_else(|| invalid(&line))?;
+                current = Some((name.to_string(), Material::default()));
+            }
+            Some("Kd") => {
+                let kd = parse_floats(words, &line)?;
+                if let (Some((_, mat)), [r, g, b]) = (current.as_mut(), &kd[..]) {
+                    let to_u8 = |v: f64| (v.clamp(0.0, 1.0) * 255.0) as u8;
+                    mat.diffuse = Color(to_u8(*r), to_u8(*g), to_u8(*b));
+                }
+            }
+            Some("Ks") => {
+                let ks = parse_floats(words, &line)?;
+                if let (Some((_, mat)), [r, g, b]) = (current.as_mut(), &ks[..]) {
+                    mat.specular = (r + g + b) / 3.0;
+                }
+            }
+            Some("map_Kd") => {
+                if let (Some((_, mat)), Some(name)) = (current.as_mut(), words.last()) {
+                    mat.texture = Some(dir.join(name).to_string_lossy().into_owned());
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if let Some((name, mat)) = current.take() {
+        materials.insert(name, mat);
+    }
+
+    Ok(materials)
+}
+
+/// Files read for a prop, with their modification time when they were read
+type Files = Vec<(PathBuf, Option<SystemTime>)>;
+
+/// Props by OBJ path, read again only when the OBJ file or one of its material
+/// libraries or textures changes
+#[derive(Default)]
+pub struct PropCache {
+    props: HashMap<String, (Files, Option<Rc<ObjectContainer>>)>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl PropCache {
+    /// Load an OBJ file, building one mesh per material used
+    pub fn load(&mut self, path: &str) -> std::io::Result<Rc<ObjectContainer>> {
+        if let Some((files, Some(props))) = self.props.get(path) {
+            if files.iter().all(|(file, time)| modified(file) == *time) {
+                return Ok(props.clone());
+            }
+        }
+
+        println!("Loading mesh from {}", path);
+
+        let mut files = vec![PathBuf::from(path)];
+        let res = File::open(path).and_then(|file| {
+            let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+            parse(BufReader::new(file), dir, &mut files)
+        });
+        let res = res.map(Rc::new);
+
+        let files = files
+            .into_iter()
+            .map(|file| {
+                let time = modified(&file);
+                (file, time)
+            })
+            .collect();
+        self.props
+            .insert(path.to_string(), (files, res.as_ref().ok().cloned()));
+        res
+    }
+
+    /// Files the props were read from, those that failed to load included
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.props
+            .values()
+            .flat_map(|(files, _)| files.iter().map(|(file, _)| file.as_path()))
+    }
+}
+
+/// Parse OBJ data, `dir` is where material libraries are looked up. The
+/// material libraries and textures read are added to `files`.
+pub fn parse<R: BufRead>(
+    reader: R,
+    dir: &Path,
+    files: &mut Vec<PathBuf>,
+) -> std::io::Result<ObjectContainer> {
+    let mut buffers = MeshBuffers::default();
+    let mut materials = HashMap::<String, Material>::new();
+
+    // Faces grouped by material, in order of appearance
+    let mut groups: Vec<(Option<String>, Vec<Face>)> = vec![(None, Vec::new())];
+
+    for line in reader.lines() {
+        let line = line?;
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            Some("v") => match parse_floats(words, &line)?[..] {
+                [x, y, z, ..] => buffers.vertices.push(Point(x, y, z)),
+                _ => return Err(invalid(&line)),
+            },
+            Some("vn") => match parse_floats(words, &line)?[..] {
+                [x, y, z] => buffers.normals.push(Vector::new(x, y, z)),
+                _ => return Err(invalid(&line)),
+            },
+            Some("vt") => match parse_floats(words, &line)?[..] {
+                // Textures are indexed from their top right corner
+                [u, v, ..] => buffers.uvs.push((1.0 - u, 1.0 - v)),
+                [u] => buffers.uvs.push((1.0 - u, 1.0)),
+                _ => return Err(invalid(&line)),
+            },
+            Some("f") => {
+                let mut corners = Vec::new();
+                for word in words {
+                    let mut parts = word.split('/');
+                    let v = parse_index(parts.next().unwrap(), buffers.vertices.len(), &line)?;
+                    let t = match parts.next() {
+                        Some("") | None => None,
+                        Some(t) => Some(parse_index(t, buffers.uvs.len(), &line)?),
+                    };
+                    let n = match parts.next() {
+                        Some("") | None => None,
+                        Some(n) => Some(parse_index(n, buffers.normals.len(), &line)?),
+                    };
+                    corners.push((v, t, n));
+                }
+
+                if corners.len() < 3 {
+                    return Err(invalid(&line));
+                }
+
+                // Triangulate polygons as a fan around the first corner
+                let faces = &mut groups.last_mut().unwrap().1;
+                for i in 1..corners.len() - 1 {
+                    let (a, b, c) = (corners[0], corners[i], corners[i + 1]);
+                    faces.push(Face {
+                        vertices: [a.0, b.0, c.0],
+                        uvs: match (a.1, b.1, c.1) {
+                            (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+                            _ => None,
+                        },
+                        normals: match (a.2, b.2, c.2) {
+                            (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+                            _ => None,
+                        },
+                    });
+                }
+            }
+            Some("mtllib") => {
+                for name in words {
+                    let path = dir.join(name);
+                    files.push(path.clone());
+                    materials.extend(parse_mtl(&path)?);
+                }
+            }
+            Some("usemtl") => {
+                let name = words.next().ok_or_else(|| invalid(&line))?;
+                groups.push((Some(name.to_string()), Vec::new()));
+            }
+            _ => (),
+        }
+    }
+
+    let buffers = Rc::new(buffers);
+    let mut res = ObjectContainer::new();
+
+    for (name, faces) in groups.into_iter() {
+        if faces.is_empty() {
+            continue;
+        }
+
+        let material = name
+            .and_then(|name| materials.get(&name).cloned())
+            .unwrap_or_default();
+
+        match material.texture {
+            Some(texture) => {
+                files.push(PathBuf::from(&texture));
+                res.push(Box::new(Mesh::new(
+                    buffers.clone(),
+                    faces,
+                    UVMapTexture::new(texture, 1.0, material.specular)?,
+                )))
+            }
+            None => res.push(Box::new(Mesh::new(
+                buffers.clone(),
+                faces,
+                UniformTexture::new(material.diffuse, 1.0, material.specular),
+            ))),
+        }
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scene::Ray;
+
+    use super::*;
+
+    fn parse_all(data: &[u8]) -> std::io::Result<ObjectContainer> {
+        parse(data, Path::new(""), &mut Vec::new())
+    }
+
+    const QUAD: &str = "
+# A unit quad facing -z, split into two materials
+v 0 0 5
+v 1 0 5
+v 1 1 5
+v 0 1 5
+vn 0 0 -1
+f 1//1 2//1 3//1
+usemtl other
+f -4//1 -2//1 -1//1
+";
+
+    #[test]
+    fn parse_groups() {
+        let objs = parse_all(QUAD.as_bytes()).unwrap();
+        assert_eq!(objs.len(), 2);
+
+        let ray = Ray {
+            energy: 1.0,
+            time: 0.0,
+            origin: Point(0.2, 0.7, 0.0),
+            direction: Vector::new(0.0, 0.0, 1.0).normalize(),
+        };
+
+        assert!(objs[0].intersects(ray).is_none());
+        assert!(objs[1].intersects(ray).is_some());
+    }
+
+    #[test]
+    fn parse_invalid_index() {
+        let data = "v 0 0 0\nv 1 0 0\nf 1 2 3\n";
+        assert!(parse_all(data.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn cache() {
+        let dir = std::env::temp_dir().join("isim_obj_cache");
+        std::fs::create_dir_all(&dir).unwrap();
+        let obj = dir.join("prop.obj");
+        std::fs::write(&obj, format!("mtllib prop.mtl\nusemtl bark\n{}", QUAD)).unwrap();
+        std::fs::write(dir.join("prop.mtl"), "newmtl bark\nmap_Kd missing.png\n").unwrap();
+
+        let mut props = PropCache::default();
+        let path = obj.to_str().unwrap();
+        assert!(props.load(path).is_err());
+        let files: Vec<&Path> = props.files().collect();
+        assert_eq!(
+            files,
+            [
+                obj.as_path(),
+                &dir.join("prop.mtl"),
+                &dir.join("missing.png")
+            ]
+        );
+
+        std::fs::write(dir.join("prop.mtl"), "newmtl bark\nKd 1 0 0\n").unwrap();
+        let loaded = props.load(path).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert!(Rc::ptr_eq(&loaded, &props.load(path).unwrap()));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::common::*;
+
+use super::{Object, Ray, TextureMaterial};
+
+pub struct Plane<T: TextureMaterial> {
+    point: Point,
+    normal: NormalVector,
+    u_axis: Vector,
+    v_axis: Vector,
+    texture: T,
+}
+
+impl<T> Plane<T>
+where
+    T: TextureMaterial,
+{
+    pub fn new(point: Point, normal: NormalVector, texture: T) -> Plane<T> {
+        let n = normal.vector();
+
+        // Any vector not colinear to the normal gives us a basis of the plane
+        let helper = if n.x.abs() > 0.9 {
+            Vector::new(0.0, 1.0, 0.0)
+        } else {
+            Vector::new(1.0, 0.0, 0.0)
+        };
+        let u_axis = Vector::cross_product(&n, &helper).normalize().vector();
+        let v_axis = Vector::cross_product(&n, &u_axis);
+
+        Plane {
+            point,
+            normal,
+            u_axis,
+            v_axis,
+            texture,
+        }
+    }
+
+    pub fn point(&self) -> Point {
+        self.point
+    }
+}
+
+impl<T> Object for Plane<T>
+where
+    T: TextureMaterial,
+{
+    fn intersects(&self, ray: Ray) -> Option<f64> {
+        let dot = NormalVector::dot_product(&self.normal, &ray.direction);
+
+        if dot < f64::EPSILON && dot > -f64::EPSILON {
+            return None;
+        }
+
+        let t =
+            Vector::dot_product(&Vector::from(ray.origin, self.point), &self.normal.vector()) / dot;
+
+        if t > 0.0 {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    fn normal(&self, _p: Point) -> NormalVector {
+        self.normal
+    }
+
+    fn diffusion(&self, p: Point) -> (f64, f64, f64) {
+        let (u, v) = self.map_to_texture(p);
+        self.texture.diffusion(u, v)
+    }
+
+    fn specularity(&self, _p: Point) -> f64 {
+        self.texture.specularity(0, 0)
+    }
+
+    fn map_to_texture(&self, p: Point) -> (f64, f64) {
+        // Coordinates are kept in world units so procedural textures tile
+        let v = Vector::from(self.point, p);
+        (
+            Vector::dot_product(&v, &self.u_axis),
+            Vector::dot_product(&v, &self.v_axis),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{ORIGIN, WHITE};
+    use crate::scene::texture::UniformTexture;
+
+    use super::*;
+
+    #[test]
+    fn intersects_true() {
+        let p = Plane::new(
+            Point(0.0, 0.0, 5.0),
+            Vector::new(0.0, 0.0, -1.0).normalize(),
+            UniformTexture::new(WHITE, 0.0, 0.0),
+        );
+
+        let ray = Ray {
+            energy: 1.0,
+            time: 0.0,
+            origin: ORIGIN,
+            direction: Vector::new(0.1, 0.2, 1.0).normalize(),
+        };
+
+        assert!(p.intersects(ray).is_some())
+    }
+
+    #[test]
+    fn intersects_false_parallel() {
+        let p = Plane::new(
+            Point(0.0, 0.0, 5.0),
+            Vector::new(0.0, 0.0, -1.0).normalize(),
+            UniformTexture::new(WHITE, 0.0, 0.0),
+        );
+
+        let ray = Ray {
+            energy: 1.0,
+            time: 0.0,
+            origin: ORIGIN,
+            direction: Vector::new(1.0, 0.0, 0.0).normalize(),
+        };
+
+        assert!(p.intersects(ray).is_none())
+    }
+
+    #[test]
+    fn intersects_false_behind() {
+        let p = Plane::new(
+            Point(0.0, 0.0, -5.0),
+            Vector::new(0.0, 0.0, 1.0).normalize(),
+            UniformTexture::new(WHITE, 0.0, 0.0),
+        );
+
+        let ray = Ray {
+            energy: 1.0,
+            time: 0.0,
+            origin: ORIGIN,
+            direction: Vector::new(0.0, 0.0, 1.0).normalize(),
+        };
+
+        assert!(p.intersects(ray).is_none())
+    }
+}
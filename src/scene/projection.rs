@@ -0,0 +1,209 @@
+use std::f64::consts::PI;
+
+use crate::{common::*, geometry::Vector};
+
+/// Mapping from the image to the rays leaving the camera
+///
+/// Screen coordinates go from `(-1, -1)` at the bottom left to `(1, 1)` at the
+/// top right. Rays are given in camera space: `x` points right, `y` up and `z`
+/// forward.
+pub trait Projection {
+    /// Origin and direction of the ray, `None` if the point is not covered by
+    /// the projection
+    fn ray(&self, sx: f64, sy: f64) -> Option<(Vector, NormalVector)>;
+
+    /// Field of view in degrees, for projections that have one
+    fn fov(&self) -> Option<f64> {
+        None
+    }
+
+    /// Change the field of view, in degrees, of projections that have one
+    fn set_fov(&mut self, _fov_angle: f64) {}
+}
+
+/// Pinhole camera, with the image plane at `z_min`
+#[derive(Debug, Clone, Copy)]
+pub struct Perspective {
+    half_width: f64,
+    half_height: f64,
+    z_min: f64,
+}
+
+impl Perspective {
+    /// `fov_angle` is the horizontal field of view in degrees, `aspect_ratio`
+    /// the width of the image over its height
+    pub fn new(fov_angle: f64, aspect_ratio: f64, z_min: f64) -> Self {
+        let half_width = z_min * (fov_angle / 2.0).to_radians().tan();
+
+        Perspective {
+            half_width,
+            half_height: half_width / aspect_ratio,
+            z_min,
+        }
+    }
+}
+
+impl Projection for Perspective {
+    fn ray(&self, sx: f64, sy: f64) -> Option<(Vector, NormalVector)> {
+        let origin = Vector::new(sx * self.half_width, sy * self.half_height, self.z_min);
+        Some((origin, origin.normalize()))
+    }
+
+    fn fov(&self) -> Option<f64> {
+        Some(2.0 * (self.half_width / self.z_min).atan().to_degrees())
+    }
+
+    fn set_fov(&mut self, fov_angle: f64) {
+        let aspect_ratio = self.half_width / self.half_height;
+        *self = Perspective::new(fov_angle, aspect_ratio, self.z_min);
+    }
+}
+
+/// Parallel rays over a `width` by `height` window
+#[derive(Debug, Clone, Copy)]
+pub struct Orthographic {
+    width: f64,
+    height: f64,
+}
+
+impl Orthographic {
+    pub fn new(width: f64, height: f64) -> Self {
+        Orthographic { width, height }
+    }
+}
+
+impl Projection for Orthographic {
+    fn ray(&self, sx: f64, sy: f64) -> Option<(Vector, NormalVector)> {
+        Some((
+            Vector::new(sx * self.width / 2.0, sy * self.height / 2.0, 0.0),
+            Vector::new(0.0, 0.0, 1.0).normalize(),
+        ))
+    }
+}
+
+/// Equidistant fisheye covering `fov_angle` degrees over the disk inscribed in
+/// the image
+#[derive(Debug, Clone, Copy)]
+pub struct Fisheye {
+    fov_angle: f64,
+    aspect_ratio: f64,
+}
+
+impl Fisheye {
+    /// `aspect_ratio` is the width of the image over its height
+    pub fn new(fov_angle: f64, aspect_ratio: f64) -> Self {
+        Fisheye {
+            fov_angle,
+            aspect_ratio,
+        }
+    }
+}
+
+impl Projection for Fisheye {
+    fn ray(&self, sx: f64, sy: f64) -> Option<(Vector, NormalVector)> {
+        // Same units on both axes, the shorter side going from -1 to 1
+        let (sx, sy) = if self.aspect_ratio >= 1.0 {
+            (sx * self.aspect_ratio, sy)
+        } else {
+            (sx, sy / self.aspect_ratio)
+        };
+        let r = (sx * sx + sy * sy).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+
+        let theta = r * self.fov_angle.to_radians() / 2.0;
+        let phi = sy.atan2(sx);
+
+        Some((
+            Vector::new(0.0, 0.0, 0.0),
+            Vector::new(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            )
+            .normalize(),
+        ))
+    }
+
+    fn fov(&self) -> Option<f64> {
+        Some(self.fov_angle)
+    }
+
+    fn set_fov(&mut self, fov_angle: f64) {
+        self.fov_angle = fov_angle;
+    }
+}
+
+/// Full 360° panorama, longitude along `x` and latitude along `y`
+#[derive(Debug, Clone, Copy)]
+pub struct Equirectangular;
+
+impl Projection for Equirectangular {
+    fn ray(&self, sx: f64, sy: f64) -> Option<(Vector, NormalVector)> {
+        let (lon, lat) = (sx * PI, sy * PI / 2.0);
+
+        Some((
+            Vector::new(0.0, 0.0, 0.0),
+            Vector::new(lat.cos() * lon.sin(), lat.sin(), lat.cos() * lon.cos()).normalize(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vector, b: Vector) -> bool {
+        (a - b).norm() < 1e-9
+    }
+
+    #[test]
+    fn perspective() {
+        let p = Perspective::new(90.0, 2.0, 2.0);
+
+        let (o, d) = p.ray(1.0, 0.0).unwrap();
+        assert!(close(o, Vector::new(2.0, 0.0, 2.0)));
+        let (o, _) = p.ray(0.0, 1.0).unwrap();
+        assert!(close(o, Vector::new(0.0, 1.0, 2.0)));
+        assert!(close(
+            d.vector(),
+            Vector::new(1.0, 0.0, 1.0).normalize().vector()
+        ));
+    }
+
+    #[test]
+    fn orthographic() {
+        let (o, d) = Orthographic::new(4.0, 2.0).ray(1.0, -1.0).unwrap();
+
+        assert!(close(o, Vector::new(2.0, -1.0, 0.0)));
+        assert!(close(d.vector(), Vector::new(0.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn fisheye() {
+        let p = Fisheye::new(180.0, 1.0);
+
+        assert!(p.ray(0.8, 0.8).is_none());
+        let (_, d) = p.ray(0.0, 1.0).unwrap();
+        assert!(close(d.vector(), Vector::new(0.0, 1.0, 0.0)));
+
+        // On a 2:1 frame the circle spans the height and half the width
+        let p = Fisheye::new(180.0, 2.0);
+        let (_, d) = p.ray(0.5, 0.0).unwrap();
+        assert!(close(d.vector(), Vector::new(1.0, 0.0, 0.0)));
+        assert!(p.ray(0.6, 0.0).is_none());
+        let (_, a) = p.ray(0.25, 0.0).unwrap();
+        let (_, b) = p.ray(0.0, 0.5).unwrap();
+        assert!((a.vector().z - b.vector().z).abs() < 1e-9);
+    }
+
+    #[test]
+    fn equirectangular() {
+        let (_, d) = Equirectangular.ray(1.0, 0.0).unwrap();
+        assert!(close(d.vector(), Vector::new(0.0, 0.0, -1.0)));
+
+        let (_, d) = Equirectangular.ray(0.5, 0.0).unwrap();
+        assert!(close(d.vector(), Vector::new(1.0, 0.0, 0.0)));
+    }
+}
@@ -3,6 +3,8 @@ use crate::{common::Point, geometry::NormalVector};
 #[derive(Clone, Copy, Debug)]
 pub struct Ray {
     pub energy: f64,
+    /// Instant within the frame, from 0 at its start to 1 at the next frame
+    pub time: f64,
     pub origin: Point,
     pub direction: NormalVector,
 }
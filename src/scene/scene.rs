@@ -1,4 +1,4 @@
-use super::{Camera, Light, Object};
+use super::{Background, Camera, Light, Object};
 
 pub type LightType = Box<dyn Light>;
 pub type ObjectType = Box<dyn Object>;
@@ -10,6 +10,7 @@ pub struct Scene {
     pub cam: Camera,
     pub lights: LightContainer,
     pub objects: ObjectContainer,
+    pub background: Background,
 }
 
 impl Scene {
@@ -18,6 +19,11 @@ impl Scene {
             cam,
             lights,
             objects,
+            background: Background::Solid(crate::common::BLACK),
         }
     }
+
+    pub fn with_background(self, background: Background) -> Scene {
+        Scene { background, ..self }
+    }
 }
@@ -1,5 +1,6 @@
 use crate::{
     common::{Point, ORIGIN},
+    geometry::BoundingBox,
     geometry::NormalVector,
     geometry::Vector,
 };
@@ -68,6 +69,10 @@ where
         self.texture.specularity(0, 0)
     }
 
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        Some(BoundingBox::from_points(&[self.center]).expand(self.radius))
+    }
+
     fn map_to_texture(&self, p: Point) -> (f64, f64) {
         let n = Vector::from(self.center, p).normalize().vector();
         let u = 0.5 + n.z.atan2(n.y) * (1.0 / (2.0 * std::f64::consts::PI));
@@ -93,6 +98,7 @@ mod tests {
 
         let ray = Ray {
             energy: 1.0,
+            time: 0.0,
             origin: ORIGIN,
             direction: Vector::new(1.0, 0.0, 0.0).normalize(),
         };
@@ -110,6 +116,7 @@ mod tests {
 
         let ray = Ray {
             energy: 1.0,
+            time: 0.0,
             origin: ORIGIN,
             direction: Vector::new(1.0, 0.0, 0.0).normalize(),
         };
@@ -1,6 +1,9 @@
 use super::TextureMaterial;
 use crate::common::{Color, Point};
-use imagelib::{DynamicImage, ImageBuffer, Rgb};
+use imagelib::{DynamicImage, ImageBuffer, Rgb, RgbImage};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::SystemTime;
 
 pub struct UniformTexture {
     color: Color,
@@ -27,18 +30,53 @@ impl TextureMaterial for UniformTexture {
 }
 
 pub struct UVMapTexture {
-    buffer: imagelib::RgbImage,
+    buffer: Rc<RgbImage>,
     diff: f64,
     refl: f64,
 }
 
 impl UVMapTexture {
-    pub fn new(name: String, diff: f64, refl: f64) -> UVMapTexture {
-        let buffer = imagelib::open(name).unwrap().to_rgb8();
+    pub fn new(name: String, diff: f64, refl: f64) -> std::io::Result<UVMapTexture> {
+        let buffer = open(&name)?;
+        Ok(UVMapTexture::from_image(Rc::new(buffer), diff, refl))
+    }
+
+    /// Texture sharing an image already loaded
+    pub fn from_image(buffer: Rc<RgbImage>, diff: f64, refl: f64) -> UVMapTexture {
         UVMapTexture { buffer, diff, refl }
     }
 }
 
+fn open(path: &str) -> std::io::Result<RgbImage> {
+    imagelib::open(path)
+        .map(|image| image.to_rgb8())
+        .map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {}", path, e))
+        })
+}
+
+/// Images of the textures by path, read again only when the file changes
+#[derive(Default)]
+pub struct TextureCache {
+    images: HashMap<String, (Option<SystemTime>, Rc<RgbImage>)>,
+}
+
+impl TextureCache {
+    pub fn load(&mut self, path: &str) -> std::io::Result<Rc<RgbImage>> {
+        let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+
+        match self.images.get(path) {
+            Some((time, image)) if *time == modified => Ok(image.clone()),
+            _ => {
+                let image = Rc::new(open(path)?);
+                self.images
+                    .insert(path.to_string(), (modified, image.clone()));
+                Ok(image)
+            }
+        }
+    }
+}
+
 impl TextureMaterial for UVMapTexture {
     fn diffusion(&self, u: f64, v: f64) -> (f64, f64, f64) {
         let mut i = (1.0 - u) * self.buffer.width() as f64;
@@ -69,3 +107,91 @@ impl TextureMaterial for UVMapTexture {
         self.refl
     }
 }
+
+/// Procedural checkerboard alternating between two colors every `size` units
+pub struct CheckerTexture {
+    even: Color,
+    odd: Color,
+    size: f64,
+    diff: f64,
+    refl: f64,
+}
+
+impl CheckerTexture {
+    pub fn new(even: Color, odd: Color, size: f64, diff: f64, refl: f64) -> CheckerTexture {
+        CheckerTexture {
+            even,
+            odd,
+            size,
+            diff,
+            refl,
+        }
+    }
+}
+
+impl TextureMaterial for CheckerTexture {
+    fn diffusion(&self, u: f64, v: f64) -> (f64, f64, f64) {
+        let cell = (u / self.size).floor() + (v / self.size).floor();
+        let Color(r, g, b) = if cell.rem_euclid(2.0) < 1.0 {
+            self.even
+        } else {
+            self.odd
+        };
+        let (r, g, b) = (r as f64, g as f64, b as f64);
+        (self.diff * r, self.diff * g, self.diff * b)
+    }
+
+    fn specularity(&self, _x: usize, _y: usize) -> f64 {
+        self.refl
+    }
+}
+
+/// Procedural grid of `line` colored lines every `size` units over `color`
+pub struct GridTexture {
+    color: Color,
+    line: Color,
+    size: f64,
+    thickness: f64,
+    diff: f64,
+    refl: f64,
+}
+
+impl GridTexture {
+    pub fn new(
+        color: Color,
+        line: Color,
+        size: f64,
+        thickness: f64,
+        diff: f64,
+        refl: f64,
+    ) -> GridTexture {
+        GridTexture {
+            color,
+            line,
+            size,
+            thickness,
+            diff,
+            refl,
+        }
+    }
+}
+
+impl TextureMaterial for GridTexture {
+    fn diffusion(&self, u: f64, v: f64) -> (f64, f64, f64) {
+        let on_line = |x: f64| {
+            let d = x.rem_euclid(self.size);
+            d < self.thickness / 2.0 || d > self.size - self.thickness / 2.0
+        };
+        let Color(r, g, b) = if on_line(u) || on_line(v) {
+            self.line
+        } else {
+            self.color
+        };
+        let (r, g, b) = (r as f64, g as f64, b as f64);
+        (self.diff * r, self.diff * g, self.diff * b)
+    }
+
+    fn specularity(&self, _x: usize, _y: usize) -> f64 {
+        self.refl
+    }
+}
@@ -0,0 +1,205 @@
+use crate::{common::*, geometry::Vector};
+
+/// Camera placement at a given time
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe {
+    pub time: f64,
+    pub pos: Point,
+    pub look_at: Point,
+    pub up: NormalVector,
+    pub fov_angle: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    /// Smooth path going through every key
+    CatmullRom,
+    /// Turn around the look-at point, interpolating the angle, distance and
+    /// height relatively to the up axis
+    Orbit,
+}
+
+/// Keyframed camera animation
+#[derive(Debug, Clone)]
+pub struct CameraTrack {
+    keys: Vec<Keyframe>,
+    interpolation: Interpolation,
+}
+
+fn lerp(a: Vector, b: Vector, t: f64) -> Vector {
+    a + (b - a) * t
+}
+
+fn catmull_rom(p0: Vector, p1: Vector, p2: Vector, p3: Vector, t: f64) -> Vector {
+    let (t2, t3) = (t * t, t * t * t);
+
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+impl CameraTrack {
+    pub fn new(interpolation: Interpolation) -> Self {
+        CameraTrack {
+            keys: Vec::new(),
+            interpolation,
+        }
+    }
+
+    /// Keys are kept sorted by time
+    pub fn with_key(mut self, key: Keyframe) -> Self {
+        let index = self.keys.partition_point(|k| k.time <= key.time);
+        self.keys.insert(index, key);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Time of the first and last keys
+    pub fn range(&self) -> (f64, f64) {
+        match (self.keys.first(), self.keys.last()) {
+            (Some(first), Some(last)) => (first.time, last.time),
+            _ => (0.0, 0.0),
+        }
+    }
+
+    /// Placement at `time`, clamped to the range of the track
+    pub fn sample(&self, time: f64) -> Keyframe {
+        assert!(!self.is_empty(), "Sampling an empty camera track");
+
+        let last = self.keys.len() - 1;
+        let i = self.keys.partition_point(|k| k.time <= time);
+        if i == 0 {
+            return Keyframe {
+                time,
+                ..self.keys[0]
+            };
+        } else if i > last {
+            return Keyframe {
+                time,
+                ..self.keys[last]
+            };
+        }
+
+        let (k1, k2) = (&self.keys[i - 1], &self.keys[i]);
+        let t = if k2.time > k1.time {
+            (time - k1.time) / (k2.time - k1.time)
+        } else {
+            0.0
+        };
+
+        let pos = |k: &Keyframe| Vector::from(ORIGIN, k.pos);
+        let look_at = |k: &Keyframe| Vector::from(ORIGIN, k.look_at);
+
+        let up = lerp(k1.up.vector(), k2.up.vector(), t).normalize();
+        let fov_angle = match (k1.fov_angle, k2.fov_angle) {
+            (Some(a), Some(b)) => Some(a + (b - a) * t),
+            (a, b) => a.or(b),
+        };
+
+        let (pos, look_at) = match self.interpolation {
+            Interpolation::Linear => (lerp(pos(k1), pos(k2), t), lerp(look_at(k1), look_at(k2), t)),
+            Interpolation::CatmullRom => {
+                let (k0, k3) = (
+                    &self.keys[i.saturating_sub(2)],
+                    &self.keys[(i + 1).min(last)],
+                );
+                (
+                    catmull_rom(pos(k0), pos(k1), pos(k2), pos(k3), t),
+                    catmull_rom(look_at(k0), look_at(k1), look_at(k2), look_at(k3), t),
+                )
+            }
+            Interpolation::Orbit => {
+                let look_at = lerp(look_at(k1), look_at(k2), t);
+                let axis = up.vector();
+
+                // Split the offsets to the look-at points into height and radial parts
+                let split = |k: &Keyframe| {
+                    let offset = Vector::from(k.look_at, k.pos);
+                    let height = Vector::dot_product(&offset, &axis);
+                    (height, offset - axis * height)
+                };
+                let ((h1, r1), (h2, r2)) = (split(k1), split(k2));
+
+                let angle = Vector::dot_product(&axis, &Vector::cross_product(&r1, &r2))
+                    .atan2(Vector::dot_product(&r1, &r2));
+                let radius = r1.norm() + (r2.norm() - r1.norm()) * t;
+                let radial = if r1.norm() > f64::EPSILON {
+                    r1.rotate(&up, angle * t) * (radius / r1.norm())
+                } else {
+                    r2 * t
+                };
+
+                (look_at + radial + axis * (h1 + (h2 - h1) * t), look_at)
+            }
+        };
+
+        Keyframe {
+            time,
+            pos: pos.to_point(),
+            look_at: look_at.to_point(),
+            up,
+            fov_angle,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f64, pos: Point) -> Keyframe {
+        Keyframe {
+            time,
+            pos,
+            look_at: ORIGIN,
+            up: Vector::new(1.0, 0.0, 0.0).normalize(),
+            fov_angle: Some(90.0),
+        }
+    }
+
+    fn track(interpolation: Interpolation) -> CameraTrack {
+        CameraTrack::new(interpolation)
+            .with_key(key(2.0, Point(0.0, 10.0, 0.0)))
+            .with_key(key(0.0, Point(0.0, 0.0, 10.0)))
+            .with_key(key(3.0, Point(0.0, 0.0, -10.0)))
+    }
+
+    fn dist(a: Point, b: Point) -> f64 {
+        Vector::from(a, b).norm()
+    }
+
+    #[test]
+    fn linear() {
+        let k = track(Interpolation::Linear).sample(1.0);
+        assert!(dist(k.pos, Point(0.0, 5.0, 5.0)) < 1e-9);
+
+        let k = track(Interpolation::Linear).sample(10.0);
+        assert!(dist(k.pos, Point(0.0, 0.0, -10.0)) < 1e-9);
+    }
+
+    #[test]
+    fn catmull_rom_through_keys() {
+        let t = track(Interpolation::CatmullRom);
+
+        assert!(dist(t.sample(0.0).pos, Point(0.0, 0.0, 10.0)) < 1e-9);
+        assert!(dist(t.sample(2.0).pos, Point(0.0, 10.0, 0.0)) < 1e-9);
+        assert!(dist(t.sample(3.0).pos, Point(0.0, 0.0, -10.0)) < 1e-9);
+    }
+
+    #[test]
+    fn orbit_keeps_distance() {
+        let t = track(Interpolation::Orbit);
+
+        for i in 0..=30 {
+            let k = t.sample(i as f64 / 10.0);
+            assert!((dist(k.pos, ORIGIN) - 10.0).abs() < 1e-9);
+        }
+        assert!(dist(t.sample(1.0).pos, Point(0.0, 50f64.sqrt(), 50f64.sqrt())) < 1e-9);
+    }
+}
@@ -0,0 +1,338 @@
+use std::borrow::Cow;
+
+use crate::{
+    common::*,
+    geometry::{BoundingBox, Matrix},
+};
+
+use super::{group::Borrowed, Group, Object, Ray};
+
+#[derive(Clone, Debug)]
+struct Transform {
+    matrix: Matrix,
+    inverse: Matrix,
+}
+
+impl Transform {
+    fn to_local_point(&self, p: Point) -> Point {
+        self.inverse.transform_point(p)
+    }
+
+    /// The ray in object space, along with the factor from world distances
+    /// to object distances
+    fn to_local_ray(&self, ray: Ray) -> (Ray, f64) {
+        let direction = self.inverse.transform_vector(ray.direction.vector());
+        let factor = direction.norm();
+
+        (
+            Ray {
+                origin: self.to_local_point(ray.origin),
+                direction: direction.normalize(),
+                ..ray
+            },
+            factor,
+        )
+    }
+
+    fn to_world_normal(&self, n: NormalVector) -> NormalVector {
+        // Normals are transformed by the inverse transpose
+        self.inverse
+            .transpose()
+            .transform_vector(n.vector())
+            .normalize()
+    }
+
+    fn intersects(&self, object: &dyn Object, ray: Ray) -> Option<f64> {
+        let (local, factor) = self.to_local_ray(ray);
+        object.intersects(local).map(|d| d / factor)
+    }
+
+    /// Blend towards `end` by `t`
+    fn lerp(&self, end: &Transform, t: f64) -> Transform {
+        let mut matrix = self.matrix;
+        for (row, end) in matrix.0.iter_mut().zip(end.matrix.0.iter()) {
+            for (m, e) in row.iter_mut().zip(end.iter()) {
+                *m += (e - *m) * t;
+            }
+        }
+
+        Transform {
+            matrix,
+            inverse: matrix.inverse().unwrap_or(self.inverse),
+        }
+    }
+}
+
+/// An object moved by an affine transform
+pub struct Transformed<O: Object> {
+    object: O,
+    transform: Transform,
+    /// Transform at the end of the frame, for moving objects
+    end: Option<Transform>,
+}
+
+/// A shared group of objects placed in the scene
+pub type Instance = Transformed<Group>;
+
+impl<O> Transformed<O>
+where
+    O: Object,
+{
+    pub fn new(object: O) -> Transformed<O> {
+        Transformed {
+            object,
+            transform: Transform {
+                matrix: Matrix::identity(),
+                inverse: Matrix::identity(),
+            },
+            end: None,
+        }
+    }
+
+    /// Transform at `time`, interpolated between the start and the end ones
+    fn at(&self, time: f64) -> Cow<'_, Transform> {
+        match &self.end {
+            Some(end) if time > 0.0 => Cow::Owned(self.transform.lerp(end, time)),
+            _ => Cow::Borrowed(&self.transform),
+        }
+    }
+
+    /// Apply the transform given by `matrix` and its inverse after the
+    /// current one
+    pub fn with_matrix(self, matrix: Matrix, inverse: Matrix) -> Transformed<O> {
+        let apply = |t: &Transform| Transform {
+            matrix: matrix * t.matrix,
+            inverse: t.inverse * inverse,
+        };
+
+        Transformed {
+            transform: apply(&self.transform),
+            end: self.end.as_ref().map(apply),
+            ..self
+        }
+    }
+
+    /// Animate the object: by the end of the frame, `matrix` is applied after
+    /// the current transform
+    pub fn with_motion(self, matrix: Matrix) -> Transformed<O> {
+        let inverse = matrix
+            .inverse()
+            .expect("Transform matrix is not invertible");
+
+        Transformed {
+            end: Some(Transform {
+                matrix: matrix * self.transform.matrix,
+                inverse: self.transform.inverse * inverse,
+            }),
+            ..self
+        }
+    }
+
+    /// Apply the transform given by `matrix` after the current one
+    pub fn with_transform(self, matrix: Matrix) -> Transformed<O> {
+        let inverse = matrix
+            .inverse()
+            .expect("Transform matrix is not invertible");
+        self.with_matrix(matrix, inverse)
+    }
+
+    pub fn with_translation(self, v: Vector) -> Transformed<O> {
+        self.with_matrix(Matrix::translation(v), Matrix::translation(-v))
+    }
+
+    pub fn with_rotation(self, axis: NormalVector, angle: f64) -> Transformed<O> {
+        self.with_matrix(
+            Matrix::rotation(&axis, angle),
+            Matrix::rotation(&axis, -angle),
+        )
+    }
+
+    pub fn with_scale(self, scale: f64) -> Transformed<O> {
+        self.with_matrix(
+            Matrix::scaling(scale, scale, scale),
+            Matrix::scaling(1.0 / scale, 1.0 / scale, 1.0 / scale),
+        )
+    }
+}
+
+/// The primitive of a transformed object hit by a ray, seen from the world
+struct TransformedSurface<'a> {
+    transform: Cow<'a, Transform>,
+    surface: Box<dyn Object + 'a>,
+}
+
+impl<'a> Object for TransformedSurface<'a> {
+    fn intersects(&self, ray: Ray) -> Option<f64> {
+        self.transform.intersects(self.surface.as_ref(), ray)
+    }
+
+    fn normal(&self, p: Point) -> NormalVector {
+        let local = self.transform.to_local_point(p);
+        self.transform.to_world_normal(self.surface.normal(local))
+    }
+
+    fn diffusion(&self, p: Point) -> (f64, f64, f64) {
+        self.surface.diffusion(self.transform.to_local_point(p))
+    }
+
+    fn specularity(&self, p: Point) -> f64 {
+        self.surface.specularity(self.transform.to_local_point(p))
+    }
+
+    fn map_to_texture(&self, p: Point) -> (f64, f64) {
+        self.surface
+            .map_to_texture(self.transform.to_local_point(p))
+    }
+}
+
+impl<O> Object for Transformed<O>
+where
+    O: Object,
+{
+    fn intersects(&self, ray: Ray) -> Option<f64> {
+        self.at(ray.time).intersects(&self.object, ray)
+    }
+
+    fn surface(&self, ray: Ray) -> Option<Box<dyn Object + '_>> {
+        let transform = self.at(ray.time);
+        let (local, _) = transform.to_local_ray(ray);
+        let surface = self
+            .object
+            .surface(local)
+            .unwrap_or_else(|| Box::new(Borrowed(&self.object)));
+
+        Some(Box::new(TransformedSurface { transform, surface }))
+    }
+
+    fn normal(&self, p: Point) -> NormalVector {
+        let local = self.transform.to_local_point(p);
+        self.transform.to_world_normal(self.object.normal(local))
+    }
+
+    fn diffusion(&self, p: Point) -> (f64, f64, f64) {
+        self.object.diffusion(self.transform.to_local_point(p))
+    }
+
+    fn specularity(&self, p: Point) -> f64 {
+        self.object.specularity(self.transform.to_local_point(p))
+    }
+
+    fn map_to_texture(&self, p: Point) -> (f64, f64) {
+        self.object.map_to_texture(self.transform.to_local_point(p))
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        // Blended transforms move each point along a segment, so the boxes at
+        // both ends contain the whole motion
+        let corners = self.object.bounding_box()?.corners();
+        let corners: Vec<Point> = std::iter::once(&self.transform)
+            .chain(self.end.iter())
+            .flat_map(|t| corners.iter().map(move |&p| t.matrix.transform_point(p)))
+            .collect();
+        Some(BoundingBox::from_points(&corners))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::common::{ORIGIN, WHITE};
+    use crate::scene::texture::UniformTexture;
+    use crate::scene::{Disk, ObjectContainer, Sphere};
+
+    use super::*;
+
+    fn instance() -> Instance {
+        let objects: ObjectContainer = vec![Box::new(Disk::new(
+            Point(1.0, 0.0, 0.0),
+            Vector::new(-1.0, 0.0, 0.0).normalize(),
+            1.0,
+            UniformTexture::new(WHITE, 0.0, 0.0),
+        ))];
+
+        // The disk ends up centered on (0, 0, 10) facing -z with a radius of 2
+        Instance::new(Group::new(Rc::new(objects)))
+            .with_rotation(Vector::new(0.0, 1.0, 0.0).normalize(), -90f64.to_radians())
+            .with_scale(2.0)
+            .with_translation(Vector::new(0.0, 0.0, 8.0))
+    }
+
+    #[test]
+    fn intersects_transformed() {
+        let ray = Ray {
+            energy: 1.0,
+            time: 0.0,
+            origin: Point(1.5, 0.0, 0.0),
+            direction: Vector::new(0.0, 0.0, 1.0).normalize(),
+        };
+
+        let d = instance().intersects(ray).unwrap();
+        assert!((d - 10.0).abs() < 1e-9)
+    }
+
+    #[test]
+    fn intersects_false_scaled_out() {
+        let ray = Ray {
+            energy: 1.0,
+            time: 0.0,
+            origin: Point(2.5, 0.0, 0.0),
+            direction: Vector::new(0.0, 0.0, 1.0).normalize(),
+        };
+
+        assert!(instance().intersects(ray).is_none())
+    }
+
+    #[test]
+    fn normal_transformed() {
+        let ray = Ray {
+            energy: 1.0,
+            time: 0.0,
+            origin: ORIGIN,
+            direction: Vector::new(0.0, 0.0, 1.0).normalize(),
+        };
+
+        let instance = instance();
+        let surface = instance.surface(ray).unwrap();
+        let n = surface.normal(Point(0.0, 0.0, 10.0)).vector();
+        assert!((n.z + 1.0).abs() < 1e-9);
+
+        let n = instance.normal(Point(0.5, 0.5, 10.0)).vector();
+        assert!((n.z + 1.0).abs() < 1e-9)
+    }
+
+    #[test]
+    fn non_uniform_scale() {
+        // Squash a sphere into an ellipsoid half as thick along z
+        let sphere = Sphere::new(ORIGIN, 1.0, UniformTexture::new(WHITE, 0.0, 0.0));
+        let ellipsoid = Transformed::new(sphere).with_matrix(
+            Matrix::scaling(1.0, 1.0, 0.5),
+            Matrix::scaling(1.0, 1.0, 2.0),
+        );
+
+        let bbox = ellipsoid.bounding_box().unwrap();
+        assert!((bbox.max.2 - 0.5).abs() < 1e-9);
+
+        // The normal at the tip of the long axis still points along it
+        let n = ellipsoid.normal(Point(1.0, 0.0, 0.0)).vector();
+        assert!((n.x - 1.0).abs() < 1e-9)
+    }
+
+    #[test]
+    fn motion() {
+        // The disk moves 4 units along z during the frame
+        let moving = instance().with_motion(Matrix::translation(Vector::new(0.0, 0.0, 4.0)));
+        let ray = |time| Ray {
+            energy: 1.0,
+            time,
+            origin: Point(1.5, 0.0, 0.0),
+            direction: Vector::new(0.0, 0.0, 1.0).normalize(),
+        };
+
+        assert!((moving.intersects(ray(0.0)).unwrap() - 10.0).abs() < 1e-9);
+        assert!((moving.intersects(ray(0.5)).unwrap() - 12.0).abs() < 1e-9);
+
+        let bbox = moving.bounding_box().unwrap();
+        assert!((bbox.min.2 - 8.0).abs() < 1e-9 && (bbox.max.2 - 16.0).abs() < 1e-9);
+    }
+}
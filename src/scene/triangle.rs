@@ -1,4 +1,4 @@
-use {super::Ray, crate::common::*};
+use {super::Ray, crate::common::*, crate::geometry::BoundingBox};
 
 use super::{Object, TextureMaterial};
 
@@ -80,6 +80,11 @@ where
         self.texture.specularity(0, 0)
     }
 
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        let (a, b, c) = self.points;
+        Some(BoundingBox::from_points(&[a, b, c]))
+    }
+
     fn map_to_texture(&self, p: Point) -> (f64, f64) {
         (0.0, 0.0)
     }
@@ -105,6 +110,7 @@ mod tests {
 
         let ray = Ray {
             energy: 1.0,
+            time: 0.0,
             origin: ORIGIN,
             direction: Vector::new(0.0, 0.0, 1.0).normalize(),
         };
@@ -125,6 +131,7 @@ mod tests {
 
         let ray = Ray {
             energy: 1.0,
+            time: 0.0,
             origin: ORIGIN,
             direction: Vector::new(1.0, 0.0, 0.0).normalize(),
         };
@@ -145,6 +152,7 @@ mod tests {
 
         let ray = Ray {
             energy: 1.0,
+            time: 0.0,
             origin: Point(2.0, 2.0, 0.0),
             direction: Vector::new(0.0, 0.0, 5.0).normalize(),
         };
@@ -0,0 +1,175 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use crate::common::*;
+use crate::post::Effect;
+use crate::scene::{CameraTrack, Interpolation, Keyframe};
+
+/// Settings read from a scene file
+///
+/// The file is made of one directive per line, `#` starting a comment:
+/// - `frames <n>`: number of frames of the animation
+/// - `track <linear|catmull-rom|orbit>`: interpolation of the camera keys
+/// - `key <time> [pos x y z] [look x y z] [up x y z] [fov f]`: camera key,
+///   missing values are taken from the previous key
+/// - `post <effect> <parameters>`: post-processing effect, applied in the
+///   order of the file, among `exposure <stops>`, `white-balance <kelvin>`,
+///   `saturation <scale>`, `bloom <threshold> <radius> <strength>`,
+///   `vignette <strength>` and `grain <amplitude>`
+#[derive(Debug, Clone, Default)]
+pub struct SceneFile {
+    pub frames: Option<usize>,
+    pub track: Option<CameraTrack>,
+    pub post: Vec<Effect>,
+}
+
+fn invalid(number: usize, line: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("line {}: invalid directive: {}", number, line),
+    )
+}
+
+fn parse_float(word: Option<&str>, number: usize, line: &str) -> std::io::Result<f64> {
+    word.and_then(|w| w.parse::<f64>().ok())
+        .ok_or_else(|| invalid(number, line))
+}
+
+fn parse_vector<'a, I: Iterator<Item = &'a str>>(
+    words: &mut I,
+    number: usize,
+    line: &str,
+) -> std::io::Result<Vector> {
+    Ok(Vector::new(
+        parse_float(words.next(), number, line)?,
+        parse_float(words.next(), number, line)?,
+        parse_float(words.next(), number, line)?,
+    ))
+}
+
+impl SceneFile {
+    pub fn load(path: &str) -> std::io::Result<SceneFile> {
+        println!("Loading scene from {}", path);
+
+        SceneFile::parse(BufReader::new(File::open(path)?))
+    }
+
+    pub fn parse<R: BufRead>(reader: R) -> std::io::Result<SceneFile> {
+        let mut res = SceneFile::default();
+
+        let mut interpolation = Interpolation::Linear;
+        let mut keys: Vec<Keyframe> = Vec::new();
+
+        for (i, line) in reader.lines().enumerate() {
+            let (number, line) = (i + 1, line?);
+            let content = line.split('#').next().unwrap();
+            let mut words = content.split_whitespace();
+
+            match words.next() {
+                None => (),
+                Some("frames") => {
+                    res.frames = Some(
+                        words
+                            .next()
+                            .and_then(|w| w.parse::<usize>().ok())
+                            .ok_or_else(|| invalid(number, &line))?,
+                    )
+                }
+                Some("track") => {
+                    interpolation = match words.next() {
+                        Some("linear") => Interpolation::Linear,
+                        Some("catmull-rom") => Interpolation::CatmullRom,
+                        Some("orbit") => Interpolation::Orbit,
+                        _ => return Err(invalid(number, &line)),
+                    }
+                }
+                Some("key") => {
+                    let time = parse_float(words.next(), number, &line)?;
+                    let (mut pos, mut look_at, mut up, mut fov_angle) = match keys.last() {
+                        Some(k) => (Some(k.pos), Some(k.look_at), k.up, k.fov_angle),
+                        None => (None, None, Vector::new(1.0, 0.0, 0.0).normalize(), None),
+                    };
+
+                    while let Some(word) = words.next() {
+                        match word {
+                            "pos" => {
+                                pos = Some(parse_vector(&mut words, number, &line)?.to_point())
+                            }
+                            "look" => {
+                                look_at = Some(parse_vector(&mut words, number, &line)?.to_point())
+                            }
+                            "up" => up = parse_vector(&mut words, number, &line)?.normalize(),
+                            "fov" => fov_angle = Some(parse_float(words.next(), number, &line)?),
+                            _ => return Err(invalid(number, &line)),
+                        }
+                    }
+
+                    match (pos, look_at) {
+                        (Some(pos), Some(look_at)) => keys.push(Keyframe {
+                            time,
+                            pos,
+                            look_at,
+                            up,
+                            fov_angle,
+                        }),
+                        _ => return Err(invalid(number, &line)),
+                    }
+                }
+                Some("post") => {
+                    let name = words.next().ok_or_else(|| invalid(number, &line))?;
+                    let params = words
+                        .map(|w| parse_float(Some(w), number, &line))
+                        .collect::<std::io::Result<Vec<f64>>>()?;
+                    res.post
+                        .push(Effect::parse(name, &params).ok_or_else(|| invalid(number, &line))?)
+                }
+                Some(_) => return Err(invalid(number, &line)),
+            }
+        }
+
+        if !keys.is_empty() {
+            res.track = Some(
+                keys.into_iter()
+                    .fold(CameraTrack::new(interpolation), |t, k| t.with_key(k)),
+            );
+        }
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_track() {
+        let data = "\
+frames 24
+track orbit # around the plant
+key 0 pos 4 0 -1 look 0 0 20 fov 90
+key 2 pos 4 20 20
+post exposure 0.5
+post bloom 0.8 4 0.3
+";
+        let scene = SceneFile::parse(data.as_bytes()).unwrap();
+        assert_eq!(scene.frames, Some(24));
+        assert_eq!(scene.post.len(), 2);
+        assert_eq!(scene.post[0], Effect::Exposure(0.5));
+
+        let track = scene.track.unwrap();
+        assert_eq!(track.range(), (0.0, 2.0));
+
+        let key = track.sample(2.0);
+        assert_eq!(key.look_at.2, 20.0);
+        assert_eq!(key.fov_angle, Some(90.0));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(SceneFile::parse("key 0 pos 1 2\n".as_bytes()).is_err());
+        assert!(SceneFile::parse("key 0 look 1 2 3\n".as_bytes()).is_err());
+        assert!(SceneFile::parse("track bezier\n".as_bytes()).is_err());
+        assert!(SceneFile::parse("post bloom 0.8\n".as_bytes()).is_err());
+    }
+}
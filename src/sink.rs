@@ -0,0 +1,330 @@
+//! Destinations of animation frames, receiving each frame as soon as it is
+//! rendered so that long animations are neither kept in memory nor lost on a
+//! crash
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use crate::image::{GifOptions, Image};
+
+pub trait FrameSink {
+    /// Whether the `index`-th frame is already stored, in which case it is
+    /// not rendered again
+    fn has_frame(&self, _index: usize) -> bool {
+        false
+    }
+
+    fn push(&mut self, index: usize, frame: Image) -> std::io::Result<()>;
+
+    /// Called once the last frame has been pushed
+    fn finish(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Error for a frame whose size differs from the first one
+fn check_size(expected: (usize, usize), frame: &Image) -> std::io::Result<()> {
+    if frame.size() == expected {
+        Ok(())
+    } else {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Frames differ in size",
+        ))
+    }
+}
+
+/// Frames kept in memory
+impl FrameSink for Vec<Image> {
+    fn push(&mut self, _index: usize, frame: Image) -> std::io::Result<()> {
+        Vec::push(self, frame);
+        Ok(())
+    }
+}
+
+/// Numbered PNG files, `out.png` giving `out_0000.png`, `out_0001.png`...
+pub struct Sequence {
+    path: PathBuf,
+    resume: bool,
+}
+
+impl Sequence {
+    pub fn new(path: &str) -> Sequence {
+        Sequence {
+            path: PathBuf::from(path),
+            resume: false,
+        }
+    }
+
+    /// Keep the frames already on disk instead of rendering them again
+    pub fn with_resume(self, resume: bool) -> Self {
+        Sequence { resume, ..self }
+    }
+
+    pub fn frame_path(&self, index: usize) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = self.path.extension().unwrap_or_else(|| "png".as_ref());
+
+        self.path
+            .with_file_name(format!("{}_{:04}", stem, index))
+            .with_extension(extension)
+    }
+}
+
+impl FrameSink for Sequence {
+    fn has_frame(&self, index: usize) -> bool {
+        self.resume && self.frame_path(index).exists()
+    }
+
+    /// The frame is written next to its path and renamed once complete, so
+    /// that a crash never leaves a truncated frame that resuming would keep
+    fn push(&mut self, index: usize, frame: Image) -> std::io::Result<()> {
+        let path = self.frame_path(index);
+        let mut partial = path.clone().into_os_string();
+        partial.push(".tmp");
+
+        let mut file = BufWriter::new(File::create(&partial)?);
+        frame.to_png(&mut file)?;
+        file.into_inner()?.sync_all()?;
+        std::fs::rename(&partial, path)
+    }
+}
+
+/// Animated GIF, the encoder being created along with the first frame
+pub struct Gif {
+    path: PathBuf,
+    options: GifOptions,
+    size: (usize, usize),
+    encoder: Option<gif::Encoder<BufWriter<File>>>,
+}
+
+impl Gif {
+    pub fn new(path: &str, options: GifOptions) -> Gif {
+        Gif {
+            path: PathBuf::from(path),
+            options,
+            size: (0, 0),
+            encoder: None,
+        }
+    }
+}
+
+impl FrameSink for Gif {
+    fn push(&mut self, _index: usize, frame: Image) -> std::io::Result<()> {
+        let encoder = match &mut self.encoder {
+            Some(encoder) => encoder,
+            None => {
+                let (width, height) = frame.size();
+                let file = BufWriter::new(File::create(&self.path)?);
+                let mut encoder = gif::Encoder::new(file, width as u16, height as u16, &[])
+                    .map_err(std::io::Error::other)?;
+                encoder
+                    .set_repeat(gif::Repeat::Infinite)
+                    .map_err(std::io::Error::other)?;
+                self.size = (width, height);
+                self.encoder.insert(encoder)
+            }
+        };
+
+        check_size(self.size, &frame)?;
+        encoder
+            .write_frame(&frame.to_gif_frame(&self.options))
+            .map_err(std::io::Error::other)
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        match self.encoder.take() {
+            Some(encoder) => encoder.into_inner().map_err(std::io::Error::other)?.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Animated PNG of a known number of frames, playing at `fps` frames per
+/// second
+pub struct Apng<W: Write> {
+    stream: Option<W>,
+    nb_frames: usize,
+    fps: f64,
+    size: (usize, usize),
+    writer: Option<png::Writer<W>>,
+}
+
+impl Apng<BufWriter<File>> {
+    pub fn create(path: &str, nb_frames: usize, fps: f64) -> std::io::Result<Self> {
+        Ok(Apng::new(
+            BufWriter::new(File::create(path)?),
+            nb_frames,
+            fps,
+        ))
+    }
+}
+
+impl<W: Write> Apng<W> {
+    pub fn new(stream: W, nb_frames: usize, fps: f64) -> Apng<W> {
+        Apng {
+            stream: Some(stream),
+            nb_frames,
+            fps,
+            size: (0, 0),
+            writer: None,
+        }
+    }
+}
+
+impl<W: Write> FrameSink for Apng<W> {
+    fn push(&mut self, _index: usize, frame: Image) -> std::io::Result<()> {
+        let writer = match (&mut self.writer, self.stream.take()) {
+            (Some(writer), _) => writer,
+            (None, Some(stream)) => {
+                let (width, height) = frame.size();
+                let mut encoder = png::Encoder::new(stream, width as u32, height as u32);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(self.nb_frames as u32, 0)?;
+                // Delay of 100 / (100 * fps) seconds
+                encoder.set_frame_delay(
+                    100,
+                    (self.fps * 100.0).round().clamp(1.0, u16::MAX as f64) as u16,
+                )?;
+                self.size = (width, height);
+                self.writer.insert(encoder.write_header()?)
+            }
+            // The stream went to an encoder whose header could not be written
+            (None, None) => {
+                return Err(std::io::Error::other(
+                    "APNG stream lost after a failed header",
+                ))
+            }
+        };
+
+        check_size(self.size, &frame)?;
+        Ok(writer.write_image_data(&frame.to_rgb())?)
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        match self.writer.take() {
+            Some(writer) => Ok(writer.finish()?),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Raw RGB frames written to the standard input of a shell command, started
+/// with the first frame after replacing `{width}`, `{height}` and `{fps}`, e.g.
+/// `ffmpeg -f rawvideo -pix_fmt rgb24 -s {width}x{height} -r {fps} -i - out.mp4`
+pub struct Pipe {
+    command: String,
+    fps: f64,
+    size: (usize, usize),
+    child: Option<(Child, BufWriter<ChildStdin>)>,
+}
+
+impl Pipe {
+    pub fn new(command: &str, fps: f64) -> Pipe {
+        Pipe {
+            command: command.to_string(),
+            fps,
+            size: (0, 0),
+            child: None,
+        }
+    }
+}
+
+impl FrameSink for Pipe {
+    fn push(&mut self, _index: usize, frame: Image) -> std::io::Result<()> {
+        let (_, stdin) = match &mut self.child {
+            Some(child) => child,
+            None => {
+                let (width, height) = frame.size();
+                self.command = self
+                    .command
+                    .replace("{width}", &width.to_string())
+                    .replace("{height}", &height.to_string())
+                    .replace("{fps}", &self.fps.to_string());
+
+                let mut child = Command::new("sh")
+                    .arg("-c")
+                    .arg(&self.command)
+                    .stdin(Stdio::piped())
+                    .spawn()?;
+                let stdin = BufWriter::new(child.stdin.take().unwrap());
+                self.size = (width, height);
+                self.child.insert((child, stdin))
+            }
+        };
+
+        check_size(self.size, &frame)?;
+        frame.write_rgb(stdin)
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        let (mut child, mut stdin) = match self.child.take() {
+            Some(child) => child,
+            None => return Ok(()),
+        };
+
+        // Closing the input tells the encoder the video is over
+        stdin.flush()?;
+        drop(stdin);
+
+        match child.wait()? {
+            status if status.success() => Ok(()),
+            status => Err(std::io::Error::other(format!(
+                "{} exited with {}",
+                self.command, status
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apng() {
+        let mut buf = Vec::new();
+        {
+            let mut sink = Apng::new(&mut buf, 2, 25.0);
+            sink.push(0, Image::new(3, 2)).unwrap();
+            assert!(sink.push(1, Image::new(2, 3)).is_err());
+            sink.push(1, Image::new(3, 2)).unwrap();
+            sink.finish().unwrap();
+        }
+
+        assert_eq!(&buf[1..4], b"PNG");
+        assert!(buf.windows(4).any(|w| w == b"acTL"));
+
+        // Later frames fail too after the header could not be written
+        let mut full = [0u8; 4];
+        let mut sink = Apng::new(&mut full[..], 2, 25.0);
+        assert!(sink.push(0, Image::new(3, 2)).is_err());
+        assert!(sink.push(1, Image::new(3, 2)).is_err());
+    }
+
+    #[test]
+    fn sequence_resume() {
+        let dir = std::env::temp_dir().join("isim_sequence_resume");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("frame.png");
+
+        let mut sink = Sequence::new(path.to_str().unwrap());
+        assert_eq!(sink.frame_path(3), dir.join("frame_0003.png"));
+        sink.push(0, Image::new(2, 2)).unwrap();
+
+        assert!(!sink.has_frame(0));
+        let sink = sink.with_resume(true);
+        assert!(sink.has_frame(0));
+        assert!(!sink.has_frame(1));
+
+        // Frames are only renamed to their path once written
+        assert!(!dir.join("frame_0000.png.tmp").exists());
+        std::fs::write(dir.join("frame_0001.png.tmp"), b"").unwrap();
+        assert!(!sink.has_frame(1));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
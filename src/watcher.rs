@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Files polled for changes through their modification time
+pub struct Watcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Watcher {
+    pub fn new<P: Into<PathBuf>, I: IntoIterator<Item = P>>(paths: I) -> Watcher {
+        Watcher {
+            files: paths
+                .into_iter()
+                .map(|p| {
+                    let path = p.into();
+                    let time = modified(&path);
+                    (path, time)
+                })
+                .collect(),
+        }
+    }
+
+    /// Also watch `path`, if not already watched
+    pub fn watch<P: Into<PathBuf>>(&mut self, path: P) {
+        let path = path.into();
+        if !self.files.iter().any(|(p, _)| *p == path) {
+            let time = modified(&path);
+            self.files.push((path, time));
+        }
+    }
+
+    /// Files modified, created or removed since the last call
+    pub fn changed_files(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (path, time) in self.files.iter_mut() {
+            let now = modified(path);
+            if now != *time {
+                *time = now;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}